@@ -0,0 +1,237 @@
+//! Client side prechecks of the SPL token accounts handed to the program, mirroring checks the
+//! program performs so they can fail before a transaction is sent
+
+use arrayref::{array_ref, array_refs};
+use solana_program::pubkey::Pubkey;
+
+use crate::error::SwapError;
+
+/// Packed length of an SPL token mint
+const MINT_LEN: usize = 82;
+/// Packed length of an SPL token account
+const ACCOUNT_LEN: usize = 165;
+/// State byte of an initialized, unfrozen SPL token account
+const ACCOUNT_STATE_INITIALIZED: u8 = 1;
+/// State byte of a frozen SPL token account
+const ACCOUNT_STATE_FROZEN: u8 = 2;
+
+/// Fields of an SPL token mint the prechecks need
+struct MintFields {
+    decimals: u8,
+    has_freeze_authority: bool,
+}
+
+/// Reads an SPL token mint, failing with [SwapError::ExpectedMint] on anything but an
+/// initialized mint
+fn unpack_mint(mint_data: &[u8]) -> Result<MintFields, SwapError> {
+    if mint_data.len() != MINT_LEN {
+        return Err(SwapError::ExpectedMint);
+    }
+    let mint_data = array_ref![mint_data, 0, MINT_LEN];
+    let (_mint_authority, _supply, decimals, is_initialized, freeze_authority) =
+        array_refs![mint_data, 36, 8, 1, 1, 36];
+    if is_initialized[0] != 1 {
+        return Err(SwapError::ExpectedMint);
+    }
+
+    Ok(MintFields {
+        decimals: decimals[0],
+        has_freeze_authority: is_some(array_ref![freeze_authority, 0, 4])
+            .ok_or(SwapError::ExpectedMint)?,
+    })
+}
+
+/// Fields of an SPL token account the prechecks need
+struct AccountFields {
+    owner: Pubkey,
+    has_delegate: bool,
+    is_frozen: bool,
+    has_close_authority: bool,
+}
+
+/// Reads an SPL token account, failing with [SwapError::ExpectedAccount] on anything but an
+/// initialized token account
+fn unpack_account(account_data: &[u8]) -> Result<AccountFields, SwapError> {
+    if account_data.len() != ACCOUNT_LEN {
+        return Err(SwapError::ExpectedAccount);
+    }
+    let account_data = array_ref![account_data, 0, ACCOUNT_LEN];
+    let (_mint, owner, _amount, delegate, state, _is_native, _delegated_amount, close_authority) =
+        array_refs![account_data, 32, 32, 8, 36, 1, 12, 8, 36];
+    if !matches!(state[0], ACCOUNT_STATE_INITIALIZED | ACCOUNT_STATE_FROZEN) {
+        return Err(SwapError::ExpectedAccount);
+    }
+
+    Ok(AccountFields {
+        owner: Pubkey::new_from_array(*owner),
+        has_delegate: is_some(array_ref![delegate, 0, 4]).ok_or(SwapError::ExpectedAccount)?,
+        is_frozen: state[0] == ACCOUNT_STATE_FROZEN,
+        has_close_authority: is_some(array_ref![close_authority, 0, 4])
+            .ok_or(SwapError::ExpectedAccount)?,
+    })
+}
+
+/// Decodes the tag of a packed `COption`, `None` if it is neither 0 nor 1
+fn is_some(tag: &[u8; 4]) -> Option<bool> {
+    match u32::from_le_bytes(*tag) {
+        0 => Some(false),
+        1 => Some(true),
+        _ => None,
+    }
+}
+
+/// Checks the token accounts handed to initialize the way the program does, returning the
+/// first violation: accounts must be owned by the authority, not frozen, not delegated and
+/// without a close authority
+pub fn precheck_init_token_accounts(
+    accounts_data: &[&[u8]],
+    authority: &Pubkey,
+) -> Result<(), SwapError> {
+    for account_data in accounts_data.iter() {
+        let account = unpack_account(account_data)?;
+        if account.owner != *authority {
+            return Err(SwapError::InvalidOwner);
+        }
+        if account.is_frozen {
+            return Err(SwapError::TokenAccountFrozen);
+        }
+        if account.has_delegate {
+            return Err(SwapError::DelegatedTokenAccount);
+        }
+        if account.has_close_authority {
+            return Err(SwapError::CloseAuthoritySet);
+        }
+    }
+    Ok(())
+}
+
+/// Checks that the pool mint has the expected decimals
+pub fn precheck_pool_mint_decimals(mint_data: &[u8], expected: u8) -> Result<(), SwapError> {
+    if unpack_mint(mint_data)?.decimals != expected {
+        return Err(SwapError::PoolTokenDecimalsInvalid);
+    }
+    Ok(())
+}
+
+/// Checks that the mint has no freeze authority, which the program rejects
+pub fn precheck_mint_no_freeze(mint_data: &[u8]) -> Result<(), SwapError> {
+    if unpack_mint(mint_data)?.has_freeze_authority {
+        return Err(SwapError::MintFreezeAuthoritySet);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Packs an initialized SPL token mint
+    fn mint_data(decimals: u8, freeze_authority: Option<Pubkey>) -> Vec<u8> {
+        let mut data = vec![0u8; MINT_LEN];
+        data[..4].copy_from_slice(&1u32.to_le_bytes());
+        data[4..36].copy_from_slice(&Pubkey::new_unique().to_bytes());
+        data[44] = decimals;
+        data[45] = 1;
+        if let Some(freeze_authority) = freeze_authority {
+            data[46..50].copy_from_slice(&1u32.to_le_bytes());
+            data[50..82].copy_from_slice(&freeze_authority.to_bytes());
+        }
+        data
+    }
+
+    #[test]
+    fn test_precheck_pool_mint_decimals() {
+        assert_eq!(precheck_pool_mint_decimals(&mint_data(6, None), 6), Ok(()));
+        assert_eq!(
+            precheck_pool_mint_decimals(&mint_data(9, None), 6),
+            Err(SwapError::PoolTokenDecimalsInvalid)
+        );
+    }
+
+    #[test]
+    fn test_precheck_pool_mint_decimals_not_a_mint() {
+        let mut uninitialized = mint_data(6, None);
+        uninitialized[45] = 0;
+        assert_eq!(
+            precheck_pool_mint_decimals(&uninitialized, 6),
+            Err(SwapError::ExpectedMint)
+        );
+        assert_eq!(
+            precheck_pool_mint_decimals(&[0u8; ACCOUNT_LEN], 6),
+            Err(SwapError::ExpectedMint)
+        );
+    }
+
+    /// Packs an initialized SPL token account owned by `owner`
+    fn account_data(owner: &Pubkey) -> Vec<u8> {
+        let mut data = vec![0u8; ACCOUNT_LEN];
+        data[..32].copy_from_slice(&Pubkey::new_unique().to_bytes());
+        data[32..64].copy_from_slice(&owner.to_bytes());
+        data[108] = ACCOUNT_STATE_INITIALIZED;
+        data
+    }
+
+    #[test]
+    fn test_precheck_init_token_accounts() {
+        let authority = Pubkey::new_unique();
+        let accounts = [account_data(&authority), account_data(&authority)];
+        let accounts: Vec<&[u8]> = accounts.iter().map(|data| &data[..]).collect();
+        assert_eq!(precheck_init_token_accounts(&accounts, &authority), Ok(()));
+    }
+
+    #[test]
+    fn test_precheck_init_token_accounts_violations() {
+        let authority = Pubkey::new_unique();
+        let valid = account_data(&authority);
+
+        let foreign = account_data(&Pubkey::new_unique());
+        let mut frozen = account_data(&authority);
+        frozen[108] = ACCOUNT_STATE_FROZEN;
+        let mut delegated = account_data(&authority);
+        delegated[72..76].copy_from_slice(&1u32.to_le_bytes());
+        delegated[76..108].copy_from_slice(&Pubkey::new_unique().to_bytes());
+        let mut closable = account_data(&authority);
+        closable[129..133].copy_from_slice(&1u32.to_le_bytes());
+        closable[133..165].copy_from_slice(&Pubkey::new_unique().to_bytes());
+        let mut uninitialized = account_data(&authority);
+        uninitialized[108] = 0;
+        let mut unknown_state = account_data(&authority);
+        unknown_state[108] = 3;
+
+        for (account, error) in [
+            (foreign, SwapError::InvalidOwner),
+            (frozen, SwapError::TokenAccountFrozen),
+            (delegated, SwapError::DelegatedTokenAccount),
+            (closable, SwapError::CloseAuthoritySet),
+            (uninitialized, SwapError::ExpectedAccount),
+            (unknown_state, SwapError::ExpectedAccount),
+        ] {
+            // the violation is reported even behind a valid account
+            assert_eq!(
+                precheck_init_token_accounts(&[&valid, &account], &authority),
+                Err(error)
+            );
+        }
+    }
+
+    #[test]
+    fn test_precheck_init_token_accounts_first_violation() {
+        let authority = Pubkey::new_unique();
+        let mut frozen = account_data(&authority);
+        frozen[108] = ACCOUNT_STATE_FROZEN;
+        let foreign = account_data(&Pubkey::new_unique());
+        assert_eq!(
+            precheck_init_token_accounts(&[&frozen, &foreign], &authority),
+            Err(SwapError::TokenAccountFrozen)
+        );
+    }
+
+    #[test]
+    fn test_precheck_mint_no_freeze() {
+        assert_eq!(precheck_mint_no_freeze(&mint_data(6, None)), Ok(()));
+        assert_eq!(
+            precheck_mint_no_freeze(&mint_data(6, Some(Pubkey::new_unique()))),
+            Err(SwapError::MintFreezeAuthoritySet)
+        );
+    }
+}
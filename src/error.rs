@@ -7,104 +7,132 @@ pub enum SwapError {
     // 0
     /// Invalid instruction
     #[error("Invalid Instruction")]
-    InvalidInstruction, // TODO: remove this, there already exists a ProgramError for this error
+    InvalidInstruction = 0, // TODO: remove this, there already exists a ProgramError for this error
     /// Not Rent Exempt
     #[error("Not Rent Exempt")]
-    NotRentExempt,
+    NotRentExempt = 1,
     /// Invalid Program Address
     #[error("Invalid Program Address")]
-    InvalidProgramAddress,
+    InvalidProgramAddress = 2,
     /// Incorrect Token Program Id
     #[error("Incorrect Token Program Id")]
-    IncorrectTokenProgramId,
+    IncorrectTokenProgramId = 3,
     /// Expected Account
     #[error("Expected Account")]
-    ExpectedAccount,
+    ExpectedAccount = 4,
 
     // 5
     /// Expected Mint
     #[error("Expected Mint")]
-    ExpectedMint,
+    ExpectedMint = 5,
     /// Invalid Owner
     #[error("Invalid Owner")]
-    InvalidOwner,
+    InvalidOwner = 6,
     /// Invalid Token Match
     #[error("Invalid Token Match")]
-    InvalidTokenMatch,
+    InvalidTokenMatch = 7,
     /// Invalid Conversion
     #[error("Invalid Conversion")]
-    InvalidConversion,
+    InvalidConversion = 8,
     /// Invalid Initial Deposit
     #[error("Invalid Initial Deposit")]
-    InvalidInitialDeposit,
+    InvalidInitialDeposit = 9,
 
     // 10
     /// Invalid Calculation
     #[error("Invalid Calculation")]
-    InvalidCalculation,
+    InvalidCalculation = 10,
     /// ExceededSlippage
     #[error("ExceededSlippage")]
-    ExceededSlippage,
+    ExceededSlippage = 11,
     /// Invalid Exchange Account
     #[error("Invalid Exchange Account")]
-    InvalidExchangeAccount,
+    InvalidExchangeAccount = 12,
     /// Invalid Mint
     #[error("Invalid Mint")]
-    InvalidMint,
+    InvalidMint = 13,
     /// Swap Already Initialized
     #[error("Swap Already Initialized")]
-    SwapAlreadyInitialized,
+    SwapAlreadyInitialized = 14,
 
     // 15
     /// Token Account Frozen
     #[error("Token Account Frozen")]
-    TokenAccountFrozen,
+    TokenAccountFrozen = 15,
     /// Delegated Token Account
     #[error("Delegated Token Account")]
-    DelegatedTokenAccount,
+    DelegatedTokenAccount = 16,
     /// Mint Freeze Authority Set
     #[error("Mint Freeze Authority Set")]
-    MintFreezeAuthoritySet,
+    MintFreezeAuthoritySet = 17,
     /// Close Authority Set
     #[error("Close Authority Set")]
-    CloseAuthoritySet,
+    CloseAuthoritySet = 18,
     /// Repeated Mint
     #[error("Repeated Mint")]
-    RepeatedMint,
+    RepeatedMint = 19,
 
     // 20
     /// Token Account Not Empty
     #[error("Token Account Not Empty")]
-    TokenAccountNotEmpty,
+    TokenAccountNotEmpty = 20,
     /// Pool Token Supply Not Empty
     #[error("Pool Token Supply Not Empty")]
-    PoolTokenSupplyNotEmpty,
+    PoolTokenSupplyNotEmpty = 21,
     /// Invalid Token Account
     #[error("Invalid Token Account")]
-    InvalidTokenAccount,
+    InvalidTokenAccount = 22,
     /// Invalid Admin Mint Decimals
     #[error("Invalid Admin Mint Decimals")]
-    InvalidAdminMintDecimals,
+    InvalidAdminMintDecimals = 23,
     /// Swap Disabled
     #[error("Swap Disabled")]
-    SwapDisabled,
+    SwapDisabled = 24,
 
     // 25
     /// Add Liquidity Disabled
     #[error("Add Liquidity Disabled")]
-    AddLiquidityDisabled,
+    AddLiquidityDisabled = 25,
     /// Admin Only
     #[error("Admin Only")]
-    NoAdminTokens,
+    NoAdminTokens = 26,
     /// Invalid Admin Delegate
     #[error("Invalid Admin Delegate")]
-    InvalidAdminDelegate,
+    InvalidAdminDelegate = 27,
     /// Admin Token Account Frozen
     #[error("Admin Token Account Frozen")]
-    AdminTokenAccountFrozen,
+    AdminTokenAccountFrozen = 28,
     /// Pool Token Decimals Invalid
     #[error("Pool Token Decimals Invalid")]
-    PoolTokenDecimalsInvalid,
+    PoolTokenDecimalsInvalid = 29,
+
+    // 30
+    /// Zero Unmint Amount
+    #[error("Zero Unmint Amount")]
+    ZeroUnmint = 30,
+    /// Invalid Precision
+    #[error("Invalid Precision")]
+    InvalidPrecision = 31,
+    /// Invalid Fee
+    #[error("Invalid Fee")]
+    InvalidFee = 32,
+    /// Invalid Number Of Coins
+    #[error("Invalid Number Of Coins")]
+    InvalidNCoins = 33,
+    /// Invalid Amplification Coefficient
+    #[error("Invalid Amplification Coefficient")]
+    InvalidAmplificationCoefficient = 34,
+
+    // 35
+    /// Zero Amount
+    #[error("Zero Amount")]
+    ZeroAmount = 35,
+    /// Unsupported Swap State Version
+    #[error("Unsupported Swap State Version")]
+    UnsupportedVersion = 36,
+    /// Invalid Slippage
+    #[error("Invalid Slippage")]
+    InvalidSlippage = 37,
 }
 
 impl From<SwapError> for ProgramError {
@@ -118,3 +146,60 @@ impl<T> DecodeError<T> for SwapError {
         "Swap Error"
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use num_traits::FromPrimitive;
+
+    #[test]
+    fn test_error_codes_are_stable() {
+        // clients hardcode these values, they must never shift
+        let codes = [
+            (SwapError::InvalidInstruction, 0),
+            (SwapError::NotRentExempt, 1),
+            (SwapError::InvalidProgramAddress, 2),
+            (SwapError::IncorrectTokenProgramId, 3),
+            (SwapError::ExpectedAccount, 4),
+            (SwapError::ExpectedMint, 5),
+            (SwapError::InvalidOwner, 6),
+            (SwapError::InvalidTokenMatch, 7),
+            (SwapError::InvalidConversion, 8),
+            (SwapError::InvalidInitialDeposit, 9),
+            (SwapError::InvalidCalculation, 10),
+            (SwapError::ExceededSlippage, 11),
+            (SwapError::InvalidExchangeAccount, 12),
+            (SwapError::InvalidMint, 13),
+            (SwapError::SwapAlreadyInitialized, 14),
+            (SwapError::TokenAccountFrozen, 15),
+            (SwapError::DelegatedTokenAccount, 16),
+            (SwapError::MintFreezeAuthoritySet, 17),
+            (SwapError::CloseAuthoritySet, 18),
+            (SwapError::RepeatedMint, 19),
+            (SwapError::TokenAccountNotEmpty, 20),
+            (SwapError::PoolTokenSupplyNotEmpty, 21),
+            (SwapError::InvalidTokenAccount, 22),
+            (SwapError::InvalidAdminMintDecimals, 23),
+            (SwapError::SwapDisabled, 24),
+            (SwapError::AddLiquidityDisabled, 25),
+            (SwapError::NoAdminTokens, 26),
+            (SwapError::InvalidAdminDelegate, 27),
+            (SwapError::AdminTokenAccountFrozen, 28),
+            (SwapError::PoolTokenDecimalsInvalid, 29),
+            (SwapError::ZeroUnmint, 30),
+            (SwapError::InvalidPrecision, 31),
+            (SwapError::InvalidFee, 32),
+            (SwapError::InvalidNCoins, 33),
+            (SwapError::InvalidAmplificationCoefficient, 34),
+            (SwapError::ZeroAmount, 35),
+            (SwapError::UnsupportedVersion, 36),
+            (SwapError::InvalidSlippage, 37),
+        ];
+        for &(error, code) in codes.iter() {
+            assert_eq!(error as u32, code, "{:?}", error);
+            assert_eq!(ProgramError::from(error), ProgramError::Custom(code));
+            assert_eq!(SwapError::from_u32(code), Some(error));
+        }
+        assert_eq!(SwapError::from_u32(codes.len() as u32), None);
+    }
+}
@@ -5,16 +5,21 @@
 //! - converting a [SwapInstruction] into byte slices
 //! - providing functions for downstream users to easily build [SwapInstruction]s
 
+use num_derive::FromPrimitive;
+use num_traits::FromPrimitive as _;
 use solana_program::instruction::AccountMeta;
 use solana_program::instruction::Instruction;
 use solana_program::program_error::ProgramError;
 use solana_program::pubkey::Pubkey;
-use std::convert::TryInto;
-use std::mem::size_of;
+use solana_program::rent::Rent;
+use solana_program::system_instruction;
+use solana_program::system_program;
+use solana_program::sysvar;
+use std::convert::{TryFrom, TryInto};
 
 use crate::check_program_account;
 use crate::error::SwapError;
-use crate::state::AdminSettings;
+use crate::state::{AdminSettings, SwapV2, SwapVersion};
 use crate::utils;
 use crate::PoolParameter;
 
@@ -29,6 +34,229 @@ pub enum AdminSetting {
     SetPrecisionMultipliers(Vec<u64>),
 }
 
+/// Maximum number of deposits in a [SwapInstruction::AddLiquidityBatch]
+pub const MAX_BATCH: usize = 4;
+
+/// Largest max_slippage_bps of a [SwapInstruction::ExchangeBps], accepting any output
+pub const MAX_SLIPPAGE_BPS: u16 = 10_000;
+
+/// Length of a packed [SwapInstruction::Initialize], which has a fixed layout: tag, nonce,
+/// n_coins, amplification coefficient, fee and admin fee numerators and the two admin settings
+pub const INITIALIZE_DATA_LEN: usize = 1 + 1 + 1 + 8 + 8 + 8 + 2;
+
+/// Tag byte leading every packed [SwapInstruction]
+#[repr(u8)]
+#[derive(Debug, PartialEq, Eq, Clone, Copy, FromPrimitive)]
+pub enum SwapInstructionTag {
+    Initialize = 0,
+    AddLiquidity = 1,
+    RemoveLiquidity = 2,
+    RemoveLiquidityOneToken = 3,
+    Exchange = 4,
+    GetVirtualPrice = 5,
+    EmergencyFreeze = 24,
+    ExchangeWithMemo = 25,
+    AddLiquidityBatch = 26,
+    Migrate = 27,
+    SetAdminSettings = 28,
+    CollectProtocolFees = 29,
+    ExchangeBps = 30,
+    SetSwapEnabled = 100,
+    SetAddLiquidityEnabled = 101,
+    SetAmplificationCoefficient = 102,
+    SetFeeNumerator = 103,
+    SetAdminFeeNumerator = 104,
+    SetPrecisionMultipliers = 105,
+}
+
+/// Anchor discriminator, `sha256("global:<instruction name>")[..8]`, of the instruction behind
+/// each [SwapInstructionTag], the admin setting tags sharing `set_admin_setting`
+const ANCHOR_DISCRIMINATORS: [(SwapInstructionTag, [u8; 8]); 19] = [
+    (
+        SwapInstructionTag::Initialize,
+        [175, 175, 109, 31, 13, 152, 155, 237],
+    ),
+    (
+        SwapInstructionTag::AddLiquidity,
+        [181, 157, 89, 67, 143, 182, 52, 72],
+    ),
+    (
+        SwapInstructionTag::RemoveLiquidity,
+        [80, 85, 209, 72, 24, 206, 177, 108],
+    ),
+    (
+        SwapInstructionTag::RemoveLiquidityOneToken,
+        [161, 148, 173, 30, 171, 254, 96, 220],
+    ),
+    (
+        SwapInstructionTag::Exchange,
+        [47, 3, 27, 97, 215, 236, 219, 144],
+    ),
+    (
+        SwapInstructionTag::GetVirtualPrice,
+        [41, 165, 98, 171, 1, 184, 61, 157],
+    ),
+    (
+        SwapInstructionTag::EmergencyFreeze,
+        [179, 69, 168, 100, 173, 7, 136, 112],
+    ),
+    (
+        SwapInstructionTag::ExchangeWithMemo,
+        [38, 189, 7, 143, 158, 5, 127, 241],
+    ),
+    (
+        SwapInstructionTag::AddLiquidityBatch,
+        [254, 87, 215, 234, 0, 131, 76, 231],
+    ),
+    (
+        SwapInstructionTag::Migrate,
+        [155, 234, 231, 146, 236, 158, 162, 30],
+    ),
+    (
+        SwapInstructionTag::SetAdminSettings,
+        [248, 144, 228, 192, 48, 0, 97, 220],
+    ),
+    (
+        SwapInstructionTag::CollectProtocolFees,
+        [22, 67, 23, 98, 150, 178, 70, 220],
+    ),
+    (
+        SwapInstructionTag::ExchangeBps,
+        [26, 139, 90, 120, 156, 202, 231, 185],
+    ),
+    (
+        SwapInstructionTag::SetSwapEnabled,
+        [161, 87, 169, 93, 70, 113, 132, 95],
+    ),
+    (
+        SwapInstructionTag::SetAddLiquidityEnabled,
+        [161, 87, 169, 93, 70, 113, 132, 95],
+    ),
+    (
+        SwapInstructionTag::SetAmplificationCoefficient,
+        [161, 87, 169, 93, 70, 113, 132, 95],
+    ),
+    (
+        SwapInstructionTag::SetFeeNumerator,
+        [161, 87, 169, 93, 70, 113, 132, 95],
+    ),
+    (
+        SwapInstructionTag::SetAdminFeeNumerator,
+        [161, 87, 169, 93, 70, 113, 132, 95],
+    ),
+    (
+        SwapInstructionTag::SetPrecisionMultipliers,
+        [161, 87, 169, 93, 70, 113, 132, 95],
+    ),
+];
+
+/// Account context of an instruction as `(name, is_writable, is_signer)`, in the order the
+/// builders emit them. Per coin accounts are described once, as a single array entry, and so
+/// are the per entry accounts of a batch.
+#[cfg(feature = "anchor-meta")]
+pub type AccountContext = Vec<(&'static str, bool, bool)>;
+
+#[cfg(feature = "anchor-meta")]
+impl SwapInstructionTag {
+    /// Describes the accounts of the instruction for Anchor style codegen
+    pub fn account_context(&self) -> AccountContext {
+        match self {
+            Self::Initialize => vec![
+                ("swap", true, false),
+                ("authority", false, false),
+                ("swap_token_accounts", false, false),
+                ("swap_token_mints", false, false),
+                ("pool_mint", false, false),
+                ("admin_token_mint", false, false),
+            ],
+            Self::AddLiquidity => vec![
+                ("swap", false, false),
+                ("token_program", false, false),
+                ("authority", false, false),
+                ("user_transfer_authority", false, true),
+                ("swap_token_accounts", true, false),
+                ("pool_mint", true, false),
+                ("source_token_accounts", true, false),
+                ("lp_token_account", true, false),
+            ],
+            Self::RemoveLiquidity => vec![
+                ("swap", false, false),
+                ("token_program", false, false),
+                ("authority", false, false),
+                ("user_transfer_authority", false, true),
+                ("swap_token_accounts", true, false),
+                ("pool_mint", true, false),
+                ("destination_token_accounts", true, false),
+                ("lp_token_account", true, false),
+            ],
+            Self::RemoveLiquidityOneToken => vec![
+                ("swap", false, false),
+                ("token_program", false, false),
+                ("authority", false, false),
+                ("user_transfer_authority", false, true),
+                ("swap_token_accounts", true, false),
+                ("pool_mint", true, false),
+                ("destination_token_account", true, false),
+                ("lp_token_account", true, false),
+            ],
+            Self::Exchange | Self::ExchangeWithMemo | Self::ExchangeBps => vec![
+                ("swap", false, false),
+                ("token_program", false, false),
+                ("authority", false, false),
+                ("user_transfer_authority", false, true),
+                ("swap_token_accounts", true, false),
+                ("source_token_account", true, false),
+                ("destination_token_account", true, false),
+            ],
+            Self::GetVirtualPrice => vec![
+                ("swap", false, false),
+                ("token_program", false, false),
+                ("swap_token_accounts", false, false),
+                ("pool_mint", false, false),
+            ],
+            Self::SetSwapEnabled
+            | Self::SetAddLiquidityEnabled
+            | Self::SetAmplificationCoefficient
+            | Self::SetFeeNumerator
+            | Self::SetAdminFeeNumerator
+            | Self::SetPrecisionMultipliers
+            | Self::EmergencyFreeze
+            | Self::SetAdminSettings => vec![
+                ("swap", true, false),
+                ("admin_token_account", false, false),
+                ("admin", false, true),
+            ],
+            Self::AddLiquidityBatch => vec![
+                ("swap", false, false),
+                ("token_program", false, false),
+                ("authority", false, false),
+                ("swap_token_accounts", true, false),
+                ("pool_mint", true, false),
+                ("user_transfer_authority", false, true),
+                ("source_token_accounts", true, false),
+                ("lp_token_account", true, false),
+            ],
+            Self::Migrate => vec![
+                ("swap", true, false),
+                ("admin_token_account", false, false),
+                ("admin", false, true),
+                ("payer", true, true),
+                ("system_program", false, false),
+                ("rent", false, false),
+            ],
+            Self::CollectProtocolFees => vec![
+                ("swap", true, false),
+                ("admin_token_account", false, false),
+                ("admin", false, true),
+                ("token_program", false, false),
+                ("authority", false, false),
+                ("swap_token_accounts", true, false),
+                ("treasury_token_account", true, false),
+            ],
+        }
+    }
+}
+
 // Instructions for the stable swap.
 #[repr(C)]
 #[derive(Debug, PartialEq, Clone)]
@@ -147,11 +375,116 @@ pub enum SwapInstruction {
     /// 1. `[T_owned by Admin NFT owner]` Admin token account
     /// 2. `[signer]` The admin NFT owner or delegate
     SetAdminSetting { admin_setting: AdminSetting },
+    /// Disables both swaps and adding liquidity in a single admin action
+    ///
+    /// Accounts expected:
+    ///
+    /// Single Signer
+    ///
+    /// 0. `[writable]` Swap state account
+    /// 1. `[T_owned by Admin NFT owner]` Admin token account
+    /// 2. `[signer]` The admin NFT owner or delegate
+    EmergencyFreeze {},
+    /// Exchanges token `i` for token `j` from the stable swap, tagged with a client memo.
+    ///
+    /// Accounts expected: same as [SwapInstruction::Exchange]
+    ///
+    ExchangeWithMemo {
+        in_amount: u64,
+        minimum_out_amount: u64,
+        /// Client defined memo, e.g. an order id
+        memo: [u8; 16],
+    },
+    /// Adds liquidity to the stable swap for several users at once.
+    ///
+    /// Accounts expected:
+    ///
+    /// 0. `[writable]` The stable swap.
+    /// 1. `[]` Token program id.
+    /// 2. `[]` The $authority.
+    /// 3. `[writable]` An array of token accounts, owned by $authority depending on N_COINS.
+    /// 4. `[writable]` The pool token mint, owned by $authority.
+    /// 5. For each entry, in order:
+    ///    - `[signer]` The user transfer authority
+    ///    - `[writable]` An array of source token accounts, owned by the LP, depending on N_COINS.
+    ///    - `[writable]` The pool token account LP tokens get sent to.
+    ///
+    AddLiquidityBatch {
+        /// The deposit amounts and expected minimum mint amount of each LP, at most [MAX_BATCH]
+        entries: Vec<(Vec<u64>, u64)>,
+    },
+    /// Migrates the swap state to the latest version, growing the account to
+    /// [SwapVersion::LATEST_LEN] and topping up its rent from the payer
+    ///
+    /// Accounts expected:
+    ///
+    /// 0. `[writable]` Swap state account
+    /// 1. `[T_owned by Admin NFT owner]` Admin token account
+    /// 2. `[signer]` The admin NFT owner or delegate
+    /// 3. `[writable, signer]` The payer of the rent top-up
+    /// 4. `[]` System program id.
+    /// 5. `[]` Rent sysvar.
+    Migrate {},
+    /// Sets both admin settings flags at once
+    ///
+    /// Accounts expected:
+    ///
+    /// Single Signer
+    ///
+    /// 0. `[writable]` Swap state account
+    /// 1. `[T_owned by Admin NFT owner]` Admin token account
+    /// 2. `[signer]` The admin NFT owner or delegate
+    SetAdminSettings { settings: AdminSettings },
+    /// Sends the accumulated protocol fees to the treasury
+    ///
+    /// Accounts expected:
+    ///
+    /// Single Signer
+    ///
+    /// 0. `[writable]` Swap state account
+    /// 1. `[T_owned by Admin NFT owner]` Admin token account
+    /// 2. `[signer]` The admin NFT owner or delegate
+    /// 3. `[]` Token program id.
+    /// 4. `[]` The $authority.
+    /// 5. `[writable]` An array of token accounts, owned by $authority depending on N_COINS.
+    /// 6. `[writable]` The treasury token account, a PDA receiving the fees.
+    CollectProtocolFees {},
+    /// Exchanges token `i` for token `j` from the stable swap, the minimum out amount being
+    /// derived by the program from its own quote.
+    ///
+    /// Accounts expected: same as [SwapInstruction::Exchange]
+    ///
+    ExchangeBps {
+        in_amount: u64,
+        /// Accepted shortfall from the quote, in basis points, at most [MAX_SLIPPAGE_BPS]
+        max_slippage_bps: u16,
+    },
 }
 
 impl SwapInstruction {
+    /// Reads the [SwapInstructionTag] of a packed [SwapInstruction] without decoding the rest
+    pub fn peek_tag(input: &[u8]) -> Result<SwapInstructionTag, ProgramError> {
+        let (&tag, _rest) = input.split_first().ok_or(SwapError::InvalidInstruction)?;
+        SwapInstructionTag::from_u8(tag).ok_or_else(|| SwapError::InvalidInstruction.into())
+    }
+
     /// Unpacks a byte buffer into a [SwapInstruction](enum.SwapInstruction.html).
     pub fn unpack(input: &[u8]) -> Result<Self, ProgramError> {
+        let instruction = Self::unpack_prefix(input)?;
+        // the memo ends the instruction, nothing may follow it
+        if let Self::ExchangeWithMemo { .. } = instruction {
+            if input.len() != instruction.expected_len() {
+                return Err(SwapError::InvalidInstruction.into());
+            }
+        }
+        Ok(instruction)
+    }
+
+    /// Unpacks the [SwapInstruction] leading a byte buffer, ignoring whatever follows it
+    fn unpack_prefix(input: &[u8]) -> Result<Self, ProgramError> {
+        if input.is_empty() {
+            return Err(SwapError::InvalidInstruction.into());
+        }
         let (tag, rest) = Self::unpack_u8(input)?;
 
         Ok(match tag {
@@ -161,8 +494,11 @@ impl SwapInstruction {
                 let (amplification_coefficient, rest) = Self::unpack_u64(rest)?;
                 let (fee_numerator, rest) = Self::unpack_u64(rest)?;
                 let (admin_fee_numerator, rest) = Self::unpack_u64(rest)?;
-                let (swap_enabled, rest) = Self::unpack_u8(rest)?;
-                let (add_liquidity_enabled, _) = Self::unpack_u8(rest)?;
+                // admin_settings are two flags, both must be present
+                if rest.len() < 2 {
+                    return Err(SwapError::InvalidInstruction.into());
+                }
+                let admin_settings = AdminSettings::try_from(&rest[..2])?;
 
                 Self::Initialize {
                     nonce,
@@ -170,22 +506,11 @@ impl SwapInstruction {
                     amplification_coefficient,
                     fee_numerator,
                     admin_fee_numerator,
-                    admin_settings: AdminSettings {
-                        swap_enabled: utils::u8_to_bool(swap_enabled)?,
-                        add_liquidity_enabled: utils::u8_to_bool(add_liquidity_enabled)?,
-                    },
+                    admin_settings,
                 }
             }
             1 => {
-                let mut deposit_amounts = Vec::with_capacity(PoolParameter::MAX_N_COINS);
-                let (length, rest) = Self::unpack_u32(rest)?;
-                for i in 0..length as usize {
-                    let (_amount, rest) = rest.split_at(i * 8);
-                    let (deposit_amount, _rest) = Self::unpack_u64(rest)?;
-                    deposit_amounts.push(deposit_amount);
-                }
-
-                let (_amount, rest) = rest.split_at(length as usize * 8);
+                let (deposit_amounts, rest) = Self::unpack_u64_vec(rest)?;
                 let (min_mint_amount, _rest) = Self::unpack_u64(rest)?;
                 Self::AddLiquidity {
                     deposit_amounts,
@@ -195,13 +520,7 @@ impl SwapInstruction {
             2 => {
                 let (unmint_amount, rest) = Self::unpack_u64(rest)?;
 
-                let mut minimum_amounts = Vec::with_capacity(PoolParameter::MAX_N_COINS);
-                let (length, rest) = Self::unpack_u32(rest)?;
-                for i in 0..length as usize {
-                    let (_amount, rest) = rest.split_at(i * 8);
-                    let (minimum_amount, _rest) = Self::unpack_u64(rest)?;
-                    minimum_amounts.push(minimum_amount);
-                }
+                let (minimum_amounts, _rest) = Self::unpack_u64_vec(rest)?;
 
                 Self::RemoveLiquidity {
                     unmint_amount,
@@ -227,6 +546,56 @@ impl SwapInstruction {
                 }
             }
             5 => Self::GetVirtualPrice {},
+            24 => Self::EmergencyFreeze {},
+            25 => {
+                let (in_amount, rest) = Self::unpack_u64(rest)?;
+                let (minimum_out_amount, rest) = Self::unpack_u64(rest)?;
+                let (memo, _rest) = Self::unpack_array16(rest)?;
+
+                Self::ExchangeWithMemo {
+                    in_amount,
+                    minimum_out_amount,
+                    memo,
+                }
+            }
+            26 => {
+                let (count, mut rest) = Self::unpack_u32(rest)?;
+                if count as usize > MAX_BATCH {
+                    return Err(SwapError::InvalidInstruction.into());
+                }
+
+                let mut entries = Vec::with_capacity(count as usize);
+                for _ in 0..count {
+                    let (deposit_amounts, entry_rest) = Self::unpack_u64_vec(rest)?;
+                    let (min_mint_amount, entry_rest) = Self::unpack_u64(entry_rest)?;
+                    entries.push((deposit_amounts, min_mint_amount));
+                    rest = entry_rest;
+                }
+
+                Self::AddLiquidityBatch { entries }
+            }
+            27 => Self::Migrate {},
+            28 => {
+                if rest.len() < 2 {
+                    return Err(SwapError::InvalidInstruction.into());
+                }
+                Self::SetAdminSettings {
+                    settings: AdminSettings::try_from(&rest[..2])?,
+                }
+            }
+            29 => Self::CollectProtocolFees {},
+            30 => {
+                let (in_amount, rest) = Self::unpack_u64(rest)?;
+                let (max_slippage_bps, _rest) = Self::unpack_u16(rest)?;
+                if max_slippage_bps > MAX_SLIPPAGE_BPS {
+                    return Err(SwapError::InvalidSlippage.into());
+                }
+
+                Self::ExchangeBps {
+                    in_amount,
+                    max_slippage_bps,
+                }
+            }
             100..=199 => Self::SetAdminSetting {
                 admin_setting: match tag {
                     100 => {
@@ -250,26 +619,327 @@ impl SwapInstruction {
                         AdminSetting::SetAdminFeeNumerator(admin_fee_numerator)
                     }
                     105 => {
-                        let mut precision_multipliers =
-                            Vec::with_capacity(PoolParameter::MAX_N_COINS);
-                        let (length, rest) = Self::unpack_u32(rest)?;
-                        for i in 0..length as usize {
-                            let (_amount, rest) = rest.split_at(i * 8);
-                            let (precision_multiplier, _rest) = Self::unpack_u64(rest)?;
-                            precision_multipliers.push(precision_multiplier);
-                        }
+                        let (precision_multipliers, _rest) = Self::unpack_u64_vec(rest)?;
                         AdminSetting::SetPrecisionMultipliers(precision_multipliers)
                     }
                     _ => return Err(ProgramError::InvalidInstructionData),
                 },
             },
-            _ => return Err(ProgramError::InvalidAccountData.into()),
+            _ => return Err(ProgramError::InvalidAccountData),
+        })
+    }
+
+    /// Unpacks a byte buffer like [SwapInstruction::unpack], reporting on failure the name of the
+    /// first field that could not be decoded, e.g. `"min_mint_amount"`
+    pub fn unpack_verbose(input: &[u8]) -> Result<Self, (SwapError, &'static str)> {
+        Self::unpack(input).map_err(|error| {
+            let error = match error {
+                ProgramError::Custom(code) => {
+                    SwapError::from_u32(code).unwrap_or(SwapError::InvalidInstruction)
+                }
+                _ => SwapError::InvalidInstruction,
+            };
+            (error, Self::undecodable_field(input))
         })
     }
 
+    /// Walks the layout [SwapInstruction::pack] writes and names the first field that cannot be
+    /// decoded from `input`
+    fn undecodable_field(input: &[u8]) -> &'static str {
+        use PackedField::*;
+
+        let (&tag, rest) = match input.split_first() {
+            Some(split) => split,
+            None => return "tag",
+        };
+        let fields: &[(&'static str, PackedField)] = match tag {
+            0 => &[
+                ("nonce", U8),
+                ("n_coins", U8),
+                ("amplification_coefficient", U64),
+                ("fee_numerator", U64),
+                ("admin_fee_numerator", U64),
+                ("swap_enabled", Bool),
+                ("add_liquidity_enabled", Bool),
+            ],
+            1 => &[("deposit_amounts", U64Vec), ("min_mint_amount", U64)],
+            2 => &[("unmint_amount", U64), ("minimum_amounts", U64Vec)],
+            3 => &[("unmint_amount", U64), ("minimum_out_amount", U64)],
+            4 => &[("in_amount", U64), ("minimum_out_amount", U64)],
+            5 | 24 | 27 | 29 => &[],
+            25 => &[
+                ("in_amount", U64),
+                ("minimum_out_amount", U64),
+                ("memo", Memo),
+            ],
+            26 => &[("entries", Batch)],
+            28 => &[("swap_enabled", Bool), ("add_liquidity_enabled", Bool)],
+            30 => &[("in_amount", U64), ("max_slippage_bps", SlippageBps)],
+            100 | 101 => &[("enabled", Bool)],
+            102 => &[("amplification_coefficient", U64)],
+            103 => &[("fee_numerator", U64)],
+            104 => &[("admin_fee_numerator", U64)],
+            105 => &[("precision_multipliers", U64Vec)],
+            _ => return "tag",
+        };
+
+        let mut rest = rest;
+        for (name, field) in fields.iter() {
+            match field.skip(rest) {
+                Some(field_rest) => rest = field_rest,
+                None => return name,
+            }
+        }
+        "instruction"
+    }
+
+    /// Unpacks the [SwapInstruction] leading a byte buffer, along with the number of bytes it
+    /// was packed in
+    pub fn unpack_with_len(input: &[u8]) -> Result<(Self, usize), ProgramError> {
+        let instruction = Self::unpack_prefix(input)?;
+        let len = instruction.expected_len();
+        Ok((instruction, len))
+    }
+
+    /// Unpacks back-to-back packed [SwapInstruction]s, failing on any trailing partial
+    /// instruction
+    pub fn unpack_all(input: &[u8]) -> Result<Vec<Self>, ProgramError> {
+        let mut instructions = Vec::new();
+        let mut rest = input;
+        while !rest.is_empty() {
+            let (instruction, len) = Self::unpack_with_len(rest)?;
+            instructions.push(instruction);
+            rest = &rest[len..];
+        }
+        Ok(instructions)
+    }
+
+    /// Recovers the [SwapInstruction] of an instruction addressed to the swap program
+    pub fn from_instruction(ix: &Instruction) -> Result<Self, ProgramError> {
+        check_program_account(&ix.program_id)?;
+        Self::unpack(&ix.data)
+    }
+
+    /// Unpacks a byte buffer prefixed with a format version byte, 0 being the current tag based
+    /// encoding
+    pub fn unpack_v(input: &[u8]) -> Result<Self, ProgramError> {
+        let (version, rest) = Self::unpack_u8(input)?;
+        match version {
+            0 => Self::unpack(rest),
+            _ => Err(SwapError::InvalidInstruction.into()),
+        }
+    }
+
+    /// Packs a [SwapInstruction] prefixed with the given format version byte
+    pub fn pack_v(&self, version: u8) -> Result<Vec<u8>, ProgramError> {
+        match version {
+            0 => {
+                let mut buf = Vec::with_capacity(1 + self.expected_len());
+                buf.push(version);
+                buf.extend_from_slice(&self.pack());
+                Ok(buf)
+            }
+            _ => Err(SwapError::InvalidInstruction.into()),
+        }
+    }
+
+    /// Unpacks a byte buffer prefixed with the 8-byte Anchor discriminator of its variant,
+    /// checking the discriminator against the tag before decoding the rest
+    pub fn unpack_anchor(input: &[u8]) -> Result<Self, ProgramError> {
+        if input.len() < 8 {
+            return Err(SwapError::InvalidInstruction.into());
+        }
+        let (discriminator, rest) = input.split_at(8);
+        let tag = Self::peek_tag(rest)?;
+        if !ANCHOR_DISCRIMINATORS
+            .iter()
+            .any(|(expected_tag, expected)| *expected_tag == tag && expected[..] == *discriminator)
+        {
+            return Err(SwapError::InvalidInstruction.into());
+        }
+        Self::unpack(rest)
+    }
+
+    /// Packs a [SwapInstruction] prefixed with the 8-byte Anchor discriminator of its variant
+    pub fn pack_anchor(&self) -> Vec<u8> {
+        let mut buf = self.anchor_discriminator().to_vec();
+        buf.extend_from_slice(&self.pack());
+        buf
+    }
+
+    /// Anchor discriminator of the variant, `sha256("global:<instruction name>")[..8]`
+    pub fn anchor_discriminator(&self) -> [u8; 8] {
+        let tag = self.tag();
+        ANCHOR_DISCRIMINATORS
+            .iter()
+            .find(|(expected_tag, _)| *expected_tag == tag)
+            .map(|(_, discriminator)| *discriminator)
+            .expect("every tag has an anchor discriminator")
+    }
+
+    /// [SwapInstructionTag] leading the packed instruction
+    fn tag(&self) -> SwapInstructionTag {
+        match self {
+            Self::Initialize { .. } => SwapInstructionTag::Initialize,
+            Self::AddLiquidity { .. } => SwapInstructionTag::AddLiquidity,
+            Self::RemoveLiquidity { .. } => SwapInstructionTag::RemoveLiquidity,
+            Self::RemoveLiquidityOneToken { .. } => SwapInstructionTag::RemoveLiquidityOneToken,
+            Self::Exchange { .. } => SwapInstructionTag::Exchange,
+            Self::GetVirtualPrice {} => SwapInstructionTag::GetVirtualPrice,
+            Self::EmergencyFreeze {} => SwapInstructionTag::EmergencyFreeze,
+            Self::ExchangeWithMemo { .. } => SwapInstructionTag::ExchangeWithMemo,
+            Self::AddLiquidityBatch { .. } => SwapInstructionTag::AddLiquidityBatch,
+            Self::Migrate {} => SwapInstructionTag::Migrate,
+            Self::SetAdminSettings { .. } => SwapInstructionTag::SetAdminSettings,
+            Self::CollectProtocolFees {} => SwapInstructionTag::CollectProtocolFees,
+            Self::ExchangeBps { .. } => SwapInstructionTag::ExchangeBps,
+            Self::SetAdminSetting { admin_setting } => match admin_setting {
+                AdminSetting::SetSwapEnabled(_) => SwapInstructionTag::SetSwapEnabled,
+                AdminSetting::SetAddLiquidityEnabled(_) => {
+                    SwapInstructionTag::SetAddLiquidityEnabled
+                }
+                AdminSetting::SetAmplificationCoefficient(_) => {
+                    SwapInstructionTag::SetAmplificationCoefficient
+                }
+                AdminSetting::SetFeeNumerator(_) => SwapInstructionTag::SetFeeNumerator,
+                AdminSetting::SetAdminFeeNumerator(_) => SwapInstructionTag::SetAdminFeeNumerator,
+                AdminSetting::SetPrecisionMultipliers(_) => {
+                    SwapInstructionTag::SetPrecisionMultipliers
+                }
+            },
+        }
+    }
+
+    /// Unpacks a base64 encoded byte buffer into a [SwapInstruction]
+    #[cfg(feature = "base64")]
+    pub fn unpack_base64(input: &str) -> Result<Self, ProgramError> {
+        use base64::Engine as _;
+
+        let input = base64::engine::general_purpose::STANDARD
+            .decode(input)
+            .map_err(|_| SwapError::InvalidInstruction)?;
+        Self::unpack(&input)
+    }
+
+    /// Decodes a packed [SwapInstruction] into a human readable breakdown of its fields and the
+    /// bytes they were read from, e.g.
+    /// `tag=4 Exchange | in_amount=1000 (bytes 1..9) | minimum_out_amount=995 (bytes 9..17)`
+    #[cfg(feature = "debug-tools")]
+    pub fn annotate(input: &[u8]) -> Result<String, ProgramError> {
+        let tag = Self::peek_tag(input)?;
+        let instruction = Self::unpack(input)?;
+
+        let mut annotation = Annotation::new(format!("tag={} {:?}", input[0], tag));
+        match &instruction {
+            Self::Initialize {
+                nonce,
+                amplification_coefficient,
+                fee_numerator,
+                admin_fee_numerator,
+                n_coins,
+                admin_settings,
+            } => {
+                annotation.field("nonce", nonce, 1);
+                annotation.field("n_coins", n_coins, 1);
+                annotation.field("amplification_coefficient", amplification_coefficient, 8);
+                annotation.field("fee_numerator", fee_numerator, 8);
+                annotation.field("admin_fee_numerator", admin_fee_numerator, 8);
+                annotation.field("swap_enabled", admin_settings.swap_enabled, 1);
+                annotation.field(
+                    "add_liquidity_enabled",
+                    admin_settings.add_liquidity_enabled,
+                    1,
+                );
+            }
+            Self::AddLiquidity {
+                deposit_amounts,
+                min_mint_amount,
+            } => {
+                annotation.u64_vec("deposit_amounts", deposit_amounts);
+                annotation.field("min_mint_amount", min_mint_amount, 8);
+            }
+            Self::RemoveLiquidity {
+                unmint_amount,
+                minimum_amounts,
+            } => {
+                annotation.field("unmint_amount", unmint_amount, 8);
+                annotation.u64_vec("minimum_amounts", minimum_amounts);
+            }
+            Self::RemoveLiquidityOneToken {
+                unmint_amount,
+                minimum_out_amount,
+            } => {
+                annotation.field("unmint_amount", unmint_amount, 8);
+                annotation.field("minimum_out_amount", minimum_out_amount, 8);
+            }
+            Self::Exchange {
+                in_amount,
+                minimum_out_amount,
+            } => {
+                annotation.field("in_amount", in_amount, 8);
+                annotation.field("minimum_out_amount", minimum_out_amount, 8);
+            }
+            Self::ExchangeWithMemo {
+                in_amount,
+                minimum_out_amount,
+                memo,
+            } => {
+                annotation.field("in_amount", in_amount, 8);
+                annotation.field("minimum_out_amount", minimum_out_amount, 8);
+                annotation.field("memo", format!("{:02x?}", memo), memo.len());
+            }
+            Self::ExchangeBps {
+                in_amount,
+                max_slippage_bps,
+            } => {
+                annotation.field("in_amount", in_amount, 8);
+                annotation.field("max_slippage_bps", max_slippage_bps, 2);
+            }
+            Self::AddLiquidityBatch { entries } => {
+                annotation.field("entries.len", entries.len(), 4);
+                for (i, (deposit_amounts, min_mint_amount)) in entries.iter().enumerate() {
+                    annotation.u64_vec(&format!("entries[{}].deposit_amounts", i), deposit_amounts);
+                    annotation.field(
+                        &format!("entries[{}].min_mint_amount", i),
+                        min_mint_amount,
+                        8,
+                    );
+                }
+            }
+            Self::SetAdminSettings { settings } => {
+                annotation.field("swap_enabled", settings.swap_enabled, 1);
+                annotation.field("add_liquidity_enabled", settings.add_liquidity_enabled, 1);
+            }
+            Self::SetAdminSetting { admin_setting } => match admin_setting {
+                AdminSetting::SetSwapEnabled(x) | AdminSetting::SetAddLiquidityEnabled(x) => {
+                    annotation.field("enabled", x, 1)
+                }
+                AdminSetting::SetAmplificationCoefficient(x)
+                | AdminSetting::SetFeeNumerator(x)
+                | AdminSetting::SetAdminFeeNumerator(x) => annotation.field("value", x, 8),
+                AdminSetting::SetPrecisionMultipliers(precision_multipliers) => {
+                    annotation.u64_vec("precision_multipliers", precision_multipliers)
+                }
+            },
+            Self::GetVirtualPrice {}
+            | Self::EmergencyFreeze {}
+            | Self::Migrate {}
+            | Self::CollectProtocolFees {} => {}
+        }
+        Ok(annotation.finish())
+    }
+
+    /// Packs a [SwapInstruction] into a base64 encoded byte buffer
+    #[cfg(feature = "base64")]
+    pub fn pack_base64(&self) -> String {
+        use base64::Engine as _;
+
+        base64::engine::general_purpose::STANDARD.encode(self.pack())
+    }
+
     /// Packs a [SwapInstruction](enum.SwapInstruction.html) into a byte buffer.
     pub fn pack(&self) -> Vec<u8> {
-        let mut buf = Vec::with_capacity(size_of::<Self>());
+        let mut buf = Vec::with_capacity(self.expected_len());
 
         match self {
             Self::Initialize {
@@ -286,8 +956,7 @@ impl SwapInstruction {
                 buf.extend_from_slice(&u64::to_le_bytes(*amplification_coefficient));
                 buf.extend_from_slice(&u64::to_le_bytes(*fee_numerator));
                 buf.extend_from_slice(&u64::to_le_bytes(*admin_fee_numerator));
-                buf.push(admin_settings.swap_enabled as u8);
-                buf.push(admin_settings.add_liquidity_enabled as u8);
+                buf.extend_from_slice(&admin_settings.to_bytes());
             }
             Self::AddLiquidity {
                 deposit_amounts,
@@ -344,16 +1013,66 @@ impl SwapInstruction {
                 buf.extend_from_slice(&minimum_out_amount.to_le_bytes());
             }
             Self::GetVirtualPrice {} => buf.push(5),
+            Self::EmergencyFreeze {} => buf.push(24),
+            Self::ExchangeWithMemo {
+                in_amount,
+                minimum_out_amount,
+                memo,
+            } => {
+                buf.push(25);
+
+                // in_amount
+                buf.extend_from_slice(&in_amount.to_le_bytes());
+
+                // minimum_out_amount
+                buf.extend_from_slice(&minimum_out_amount.to_le_bytes());
+
+                // memo
+                buf.extend_from_slice(memo);
+            }
+            Self::AddLiquidityBatch { entries } => {
+                buf.push(26);
+
+                buf.extend_from_slice(&(entries.len() as u32).to_le_bytes());
+                for (deposit_amounts, min_mint_amount) in entries.iter() {
+                    // deposit amounts
+                    buf.extend_from_slice(&(deposit_amounts.len() as u32).to_le_bytes());
+                    for deposit_amount in deposit_amounts.iter() {
+                        buf.extend_from_slice(&deposit_amount.to_le_bytes());
+                    }
+
+                    // min_mint_amount
+                    buf.extend_from_slice(&min_mint_amount.to_le_bytes());
+                }
+            }
+            Self::Migrate {} => buf.push(27),
+            Self::SetAdminSettings { settings } => {
+                buf.push(28);
+                buf.extend_from_slice(&settings.to_bytes());
+            }
+            Self::CollectProtocolFees {} => buf.push(29),
+            Self::ExchangeBps {
+                in_amount,
+                max_slippage_bps,
+            } => {
+                buf.push(30);
+
+                // in_amount
+                buf.extend_from_slice(&in_amount.to_le_bytes());
+
+                // max_slippage_bps
+                Self::pack_u16(&mut buf, *max_slippage_bps);
+            }
             Self::SetAdminSetting {
                 admin_setting: setting,
             } => match setting {
                 AdminSetting::SetSwapEnabled(x) => {
                     buf.push(100);
-                    buf.push(*x as u8)
+                    buf.push(utils::bool_to_u8(*x))
                 }
                 AdminSetting::SetAddLiquidityEnabled(x) => {
                     buf.push(101);
-                    buf.push(*x as u8)
+                    buf.push(utils::bool_to_u8(*x))
                 }
                 AdminSetting::SetAmplificationCoefficient(amplification_coefficient) => {
                     buf.push(102);
@@ -380,11 +1099,134 @@ impl SwapInstruction {
         buf
     }
 
+    /// Conservative compute unit estimate for a `ComputeBudget` request. These are heuristics,
+    /// not measurements: curve operations pay for their Newton iterations and liquidity
+    /// operations scale with the number of coins transferred.
+    pub fn estimated_compute_units(&self) -> u32 {
+        let add_liquidity = |n_coins: usize| 40_000 + 20_000 * n_coins as u32;
+        match self {
+            Self::Initialize { n_coins, .. } => 30_000 + 10_000 * *n_coins as u32,
+            Self::AddLiquidity {
+                deposit_amounts, ..
+            } => add_liquidity(deposit_amounts.len()),
+            Self::RemoveLiquidity {
+                minimum_amounts, ..
+            } => 20_000 + 10_000 * minimum_amounts.len() as u32,
+            Self::RemoveLiquidityOneToken { .. } => 80_000,
+            Self::Exchange { .. } | Self::ExchangeWithMemo { .. } => 60_000,
+            Self::ExchangeBps { .. } => 80_000,
+            Self::GetVirtualPrice {} => 30_000,
+            Self::SetAdminSetting { .. }
+            | Self::EmergencyFreeze {}
+            | Self::SetAdminSettings { .. } => 10_000,
+            Self::AddLiquidityBatch { entries } => entries
+                .iter()
+                .map(|(deposit_amounts, _)| add_liquidity(deposit_amounts.len()))
+                .sum(),
+            Self::Migrate {} => 20_000,
+            Self::CollectProtocolFees {} => 10_000 + 10_000 * PoolParameter::MAX_N_COINS as u32,
+        }
+    }
+
+    /// Exact length of the packed [SwapInstruction], including its tag
+    pub fn expected_len(&self) -> usize {
+        1 + match self {
+            Self::Initialize { .. } => INITIALIZE_DATA_LEN - 1,
+            Self::AddLiquidity {
+                deposit_amounts, ..
+            } => 4 + deposit_amounts.len() * 8 + 8,
+            Self::RemoveLiquidity {
+                minimum_amounts, ..
+            } => 8 + 4 + minimum_amounts.len() * 8,
+            Self::RemoveLiquidityOneToken { .. } => 8 + 8,
+            Self::Exchange { .. } => 8 + 8,
+            Self::GetVirtualPrice {} => 0,
+            Self::SetAdminSetting { admin_setting } => match admin_setting {
+                AdminSetting::SetSwapEnabled(_) | AdminSetting::SetAddLiquidityEnabled(_) => 1,
+                AdminSetting::SetAmplificationCoefficient(_)
+                | AdminSetting::SetFeeNumerator(_)
+                | AdminSetting::SetAdminFeeNumerator(_) => 8,
+                AdminSetting::SetPrecisionMultipliers(precision_multipliers) => {
+                    4 + precision_multipliers.len() * 8
+                }
+            },
+            Self::EmergencyFreeze {} => 0,
+            Self::ExchangeWithMemo { .. } => 8 + 8 + 16,
+            Self::AddLiquidityBatch { entries } => {
+                4 + entries
+                    .iter()
+                    .map(|(deposit_amounts, _)| 4 + deposit_amounts.len() * 8 + 8)
+                    .sum::<usize>()
+            }
+            Self::Migrate {} => 0,
+            Self::SetAdminSettings { .. } => 2,
+            Self::CollectProtocolFees {} => 0,
+            Self::ExchangeBps { .. } => 8 + 2,
+        }
+    }
+
+    /// Packs a [SwapInstruction] like [SwapInstruction::pack], failing instead of truncating
+    /// lengths when a vector holds more than [PoolParameter::MAX_N_COINS] amounts
+    pub fn try_pack(&self) -> Result<Vec<u8>, ProgramError> {
+        if let Self::AddLiquidityBatch { entries } = self {
+            if entries.len() > MAX_BATCH {
+                return Err(SwapError::InvalidInstruction.into());
+            }
+        }
+
+        let fits = |len: usize| len <= PoolParameter::MAX_N_COINS;
+        let valid = match self {
+            Self::AddLiquidity {
+                deposit_amounts, ..
+            } => fits(deposit_amounts.len()),
+            Self::RemoveLiquidity {
+                minimum_amounts, ..
+            } => fits(minimum_amounts.len()),
+            Self::SetAdminSetting {
+                admin_setting: AdminSetting::SetPrecisionMultipliers(precision_multipliers),
+            } => fits(precision_multipliers.len()),
+            Self::AddLiquidityBatch { entries } => entries
+                .iter()
+                .all(|(deposit_amounts, _)| fits(deposit_amounts.len())),
+            _ => true,
+        };
+        if !valid {
+            return Err(SwapError::InvalidNCoins.into());
+        }
+        Ok(self.pack())
+    }
+
+    /// Checks that packing this [SwapInstruction] yields exactly the given bytes
+    pub fn bytes_equal(&self, other_bytes: &[u8]) -> bool {
+        self.pack() == other_bytes
+    }
+
     fn unpack_u8(input: &[u8]) -> Result<(u8, &[u8]), ProgramError> {
         let (&amount, rest) = input.split_first().ok_or(SwapError::InvalidInstruction)?;
         Ok((amount, rest))
     }
 
+    /// Unpacks a little-endian u16 such as a basis points field, the inverse of
+    /// [SwapInstruction::pack_u16]
+    pub fn unpack_u16(input: &[u8]) -> Result<(u16, &[u8]), ProgramError> {
+        if input.len() >= 2 {
+            let (amount, rest) = input.split_at(2);
+            let amount = amount
+                .get(..2)
+                .and_then(|slice| slice.try_into().ok())
+                .map(u16::from_le_bytes)
+                .ok_or(SwapError::InvalidInstruction)?;
+            Ok((amount, rest))
+        } else {
+            Err(SwapError::InvalidInstruction.into())
+        }
+    }
+
+    /// Packs a little-endian u16 such as a basis points field
+    pub fn pack_u16(buf: &mut Vec<u8>, value: u16) {
+        buf.extend_from_slice(&value.to_le_bytes());
+    }
+
     fn unpack_u32(input: &[u8]) -> Result<(u32, &[u8]), ProgramError> {
         if input.len() >= 4 {
             let (amount, rest) = input.split_at(4);
@@ -412,16 +1254,172 @@ impl SwapInstruction {
             Err(SwapError::InvalidInstruction.into())
         }
     }
-}
 
-/// Creates a [SwapInstruction::Initialize] instruction
-pub fn initialize(
-    program_id: &Pubkey,
-    swap_account_address: &Pubkey,
-    pool_authority_address: &Pubkey,
-    swap_token_accounts_addresses: Vec<&Pubkey>,
-    swap_token_mint_addresses: Vec<&Pubkey>,
-    pool_token_mint_address: &Pubkey,
+    /// Unpacks a u32 length followed by that many u64, at most [PoolParameter::MAX_N_COINS]
+    fn unpack_u64_vec(input: &[u8]) -> Result<(Vec<u64>, &[u8]), ProgramError> {
+        let (length, mut rest) = Self::unpack_u32(input)?;
+        if length as usize > PoolParameter::MAX_N_COINS {
+            return Err(SwapError::InvalidInstruction.into());
+        }
+
+        let mut amounts = Vec::with_capacity(length as usize);
+        for _ in 0..length {
+            let (amount, amount_rest) = Self::unpack_u64(rest)?;
+            amounts.push(amount);
+            rest = amount_rest;
+        }
+        Ok((amounts, rest))
+    }
+
+    fn unpack_array16(input: &[u8]) -> Result<([u8; 16], &[u8]), ProgramError> {
+        if input.len() >= 16 {
+            let (array, rest) = input.split_at(16);
+            let array = array
+                .try_into()
+                .map_err(|_| SwapError::InvalidInstruction)?;
+            Ok((array, rest))
+        } else {
+            Err(SwapError::InvalidInstruction.into())
+        }
+    }
+}
+
+/// Field by field breakdown of a packed instruction, see [SwapInstruction::annotate]
+#[cfg(feature = "debug-tools")]
+struct Annotation {
+    out: String,
+    offset: usize,
+}
+
+#[cfg(feature = "debug-tools")]
+impl Annotation {
+    /// Starts right after the tag byte
+    fn new(tag: String) -> Self {
+        Self {
+            out: tag,
+            offset: 1,
+        }
+    }
+
+    fn field(&mut self, name: &str, value: impl std::fmt::Display, len: usize) {
+        self.out.push_str(&format!(
+            " | {}={} (bytes {}..{})",
+            name,
+            value,
+            self.offset,
+            self.offset + len
+        ));
+        self.offset += len;
+    }
+
+    /// A u32 length prefix followed by the u64 values
+    fn u64_vec(&mut self, name: &str, values: &[u64]) {
+        self.field(&format!("{}.len", name), values.len(), 4);
+        for (i, value) in values.iter().enumerate() {
+            self.field(&format!("{}[{}]", name, i), value, 8);
+        }
+    }
+
+    fn finish(self) -> String {
+        self.out
+    }
+}
+
+/// Kinds of fields of a packed [SwapInstruction], see [SwapInstruction::unpack_verbose]
+#[derive(Clone, Copy)]
+enum PackedField {
+    U8,
+    Bool,
+    U64,
+    SlippageBps,
+    /// u32 length followed by at most [PoolParameter::MAX_N_COINS] u64
+    U64Vec,
+    /// 16 bytes ending the instruction
+    Memo,
+    /// u32 count followed by at most [MAX_BATCH] deposit amounts and minimum mint amount pairs
+    Batch,
+}
+
+impl PackedField {
+    /// Skips over the field, `None` if it cannot be decoded
+    fn skip(self, input: &[u8]) -> Option<&[u8]> {
+        match self {
+            Self::U8 => input.get(1..),
+            Self::Bool => match input.split_first() {
+                Some((0..=1, rest)) => Some(rest),
+                _ => None,
+            },
+            Self::U64 => input.get(8..),
+            Self::SlippageBps => {
+                let (bps, rest) = SwapInstruction::unpack_u16(input).ok()?;
+                (bps <= MAX_SLIPPAGE_BPS).then_some(rest)
+            }
+            Self::U64Vec => SwapInstruction::unpack_u64_vec(input)
+                .ok()
+                .map(|(_, rest)| rest),
+            Self::Memo => input.get(16..).filter(|_| input.len() == 16),
+            Self::Batch => {
+                let (count, mut rest) = SwapInstruction::unpack_u32(input).ok()?;
+                if count as usize > MAX_BATCH {
+                    return None;
+                }
+                for _ in 0..count {
+                    rest = Self::U64.skip(Self::U64Vec.skip(rest)?)?;
+                }
+                Some(rest)
+            }
+        }
+    }
+}
+
+/// Checks that the swap program id was not passed where the token program is expected
+fn check_token_program_account(
+    program_id: &Pubkey,
+    token_program_address: &Pubkey,
+) -> Result<(), ProgramError> {
+    if token_program_address == program_id {
+        return Err(SwapError::IncorrectTokenProgramId.into());
+    }
+    Ok(())
+}
+
+/// Creates the leading metas shared by every admin instruction: the writable swap state, the
+/// admin token account and the signing admin NFT owner or delegate
+fn admin_account_metas(
+    swap_account_address: &Pubkey,
+    admin_token_account_address: &Pubkey,
+    nft_owner_address: &Pubkey,
+) -> Vec<AccountMeta> {
+    vec![
+        AccountMeta::new(*swap_account_address, false),
+        AccountMeta::new_readonly(*admin_token_account_address, false),
+        AccountMeta::new_readonly(*nft_owner_address, true),
+    ]
+}
+
+/// Creates the metas for a list of token accounts, all sharing the same writability
+pub fn token_account_metas(accounts: &[&Pubkey], writable: bool) -> Vec<AccountMeta> {
+    accounts
+        .iter()
+        .map(|account| {
+            if writable {
+                AccountMeta::new(**account, false)
+            } else {
+                AccountMeta::new_readonly(**account, false)
+            }
+        })
+        .collect()
+}
+
+/// Creates a [SwapInstruction::Initialize] instruction
+#[allow(clippy::too_many_arguments)]
+pub fn initialize(
+    program_id: &Pubkey,
+    swap_account_address: &Pubkey,
+    pool_authority_address: &Pubkey,
+    swap_token_accounts_addresses: Vec<&Pubkey>,
+    swap_token_mint_addresses: Vec<&Pubkey>,
+    pool_token_mint_address: &Pubkey,
     admin_token_mint_address: &Pubkey,
     nonce: u8,
     n_coins: u8,
@@ -431,35 +1429,132 @@ pub fn initialize(
     admin_settings: AdminSettings,
 ) -> Result<Instruction, ProgramError> {
     check_program_account(program_id)?; // TODO: taken from token program but can we remove this? if it only accepts 1 program_id why not just hardcode it?
+    if swap_token_accounts_addresses.len() != n_coins as usize
+        || swap_token_mint_addresses.len() != n_coins as usize
+    {
+        return Err(SwapError::InvalidNCoins.into());
+    }
+    for (i, token_account) in swap_token_accounts_addresses.iter().enumerate() {
+        if swap_token_accounts_addresses[..i].contains(token_account) {
+            return Err(SwapError::InvalidTokenAccount.into());
+        }
+    }
 
     let mut accounts = Vec::with_capacity(3 + PoolParameter::MAX_N_COINS);
     accounts.push(AccountMeta::new(*swap_account_address, false));
     accounts.push(AccountMeta::new_readonly(*pool_authority_address, false));
-    for token_account in swap_token_accounts_addresses {
-        accounts.push(AccountMeta::new_readonly(*token_account, false));
-    }
-    for token_mint_address in swap_token_mint_addresses {
-        accounts.push(AccountMeta::new_readonly(*token_mint_address, false));
-    }
+    accounts.extend(token_account_metas(&swap_token_accounts_addresses, false));
+    accounts.extend(token_account_metas(&swap_token_mint_addresses, false));
     accounts.push(AccountMeta::new_readonly(*pool_token_mint_address, false));
     accounts.push(AccountMeta::new_readonly(*admin_token_mint_address, false));
 
+    let data = SwapInstruction::Initialize {
+        nonce,
+        n_coins,
+        amplification_coefficient,
+        fee_numerator,
+        admin_fee_numerator,
+        admin_settings,
+    }
+    .pack();
+    debug_assert_eq!(data.len(), INITIALIZE_DATA_LEN);
+
     Ok(Instruction {
         program_id: *program_id,
         accounts,
-        data: SwapInstruction::Initialize {
-            nonce,
-            n_coins,
-            amplification_coefficient,
-            fee_numerator,
-            admin_fee_numerator,
-            admin_settings,
-        }
-        .pack(),
+        data,
     })
 }
 
+/// Creates the system account creation for the swap state, sized to [SwapVersion::LATEST_LEN],
+/// followed by the [SwapInstruction::Initialize] instruction
+#[allow(clippy::too_many_arguments)]
+pub fn create_and_initialize(
+    program_id: &Pubkey,
+    payer_address: &Pubkey,
+    swap_account_address: &Pubkey,
+    rent_lamports: u64,
+    pool_authority_address: &Pubkey,
+    swap_token_accounts_addresses: Vec<&Pubkey>,
+    swap_token_mint_addresses: Vec<&Pubkey>,
+    pool_token_mint_address: &Pubkey,
+    admin_token_mint_address: &Pubkey,
+    nonce: u8,
+    n_coins: u8,
+    amplification_coefficient: u64,
+    fee_numerator: u64,
+    admin_fee_numerator: u64,
+    admin_settings: AdminSettings,
+) -> Result<[Instruction; 2], ProgramError> {
+    let create_account = system_instruction::create_account(
+        payer_address,
+        swap_account_address,
+        rent_lamports,
+        SwapVersion::LATEST_LEN as u64,
+        program_id,
+    );
+    let initialize = initialize(
+        program_id,
+        swap_account_address,
+        pool_authority_address,
+        swap_token_accounts_addresses,
+        swap_token_mint_addresses,
+        pool_token_mint_address,
+        admin_token_mint_address,
+        nonce,
+        n_coins,
+        amplification_coefficient,
+        fee_numerator,
+        admin_fee_numerator,
+        admin_settings,
+    )?;
+
+    Ok([create_account, initialize])
+}
+
+/// Creates a [SwapInstruction::Initialize] instruction unless the existing swap account data
+/// already holds an initialized swap, so that deployment scripts can be re-run
+#[allow(clippy::too_many_arguments)]
+pub fn initialize_if_needed(
+    existing_state: Option<&[u8]>,
+    program_id: &Pubkey,
+    swap_account_address: &Pubkey,
+    pool_authority_address: &Pubkey,
+    swap_token_accounts_addresses: Vec<&Pubkey>,
+    swap_token_mint_addresses: Vec<&Pubkey>,
+    pool_token_mint_address: &Pubkey,
+    admin_token_mint_address: &Pubkey,
+    nonce: u8,
+    n_coins: u8,
+    amplification_coefficient: u64,
+    fee_numerator: u64,
+    admin_fee_numerator: u64,
+    admin_settings: AdminSettings,
+) -> Result<Option<Instruction>, ProgramError> {
+    if matches!(existing_state, Some(state) if SwapVersion::is_initialized(state)) {
+        return Ok(None);
+    }
+
+    initialize(
+        program_id,
+        swap_account_address,
+        pool_authority_address,
+        swap_token_accounts_addresses,
+        swap_token_mint_addresses,
+        pool_token_mint_address,
+        admin_token_mint_address,
+        nonce,
+        n_coins,
+        amplification_coefficient,
+        fee_numerator,
+        admin_fee_numerator,
+        admin_settings,
+    )
+    .map(Some)
+}
+
 /// Creates a [SwapInstruction::AddLiquidity] instruction
+#[allow(clippy::too_many_arguments)]
 pub fn add_liquidity(
     program_id: &Pubkey,
     swap_account_address: &Pubkey,
@@ -474,6 +1569,10 @@ pub fn add_liquidity(
     min_mint_amount: u64,
 ) -> Result<Instruction, ProgramError> {
     check_program_account(program_id)?; // TODO: taken from token program but can we remove this? if it only accepts 1 program_id why not just hardcode it?
+    check_token_program_account(program_id, token_program_address)?;
+    if source_token_addresses.len() != swap_token_addresses.len() {
+        return Err(SwapError::InvalidNCoins.into());
+    }
 
     let mut accounts = Vec::with_capacity(2 * PoolParameter::MAX_N_COINS + 5);
     accounts.push(AccountMeta::new_readonly(*swap_account_address, false));
@@ -483,13 +1582,9 @@ pub fn add_liquidity(
         *user_transfer_authority_address,
         true,
     ));
-    for token_account_address in swap_token_addresses {
-        accounts.push(AccountMeta::new(*token_account_address, false));
-    }
+    accounts.extend(token_account_metas(&swap_token_addresses, true));
     accounts.push(AccountMeta::new(*pool_token_mint_address, false));
-    for token_account_address in source_token_addresses {
-        accounts.push(AccountMeta::new(*token_account_address, false));
-    }
+    accounts.extend(token_account_metas(&source_token_addresses, true));
     accounts.push(AccountMeta::new(*lp_token_account_address, false));
 
     Ok(Instruction {
@@ -499,11 +1594,103 @@ pub fn add_liquidity(
             deposit_amounts,
             min_mint_amount,
         }
-        .pack(),
+        .try_pack()?,
+    })
+}
+
+/// Creates a [SwapInstruction::AddLiquidity] instruction like [add_liquidity], padding short
+/// deposit amounts with zeros up to one amount per coin
+#[allow(clippy::too_many_arguments)]
+pub fn add_liquidity_padded(
+    program_id: &Pubkey,
+    swap_account_address: &Pubkey,
+    token_program_address: &Pubkey,
+    pool_authority_address: &Pubkey,
+    user_transfer_authority_address: &Pubkey,
+    swap_token_addresses: Vec<&Pubkey>,
+    pool_token_mint_address: &Pubkey,
+    source_token_addresses: Vec<&Pubkey>,
+    lp_token_account_address: &Pubkey,
+    deposit_amounts: Vec<u64>,
+    min_mint_amount: u64,
+) -> Result<Instruction, ProgramError> {
+    let deposit_amounts = utils::pad_amounts(deposit_amounts, swap_token_addresses.len())?;
+    add_liquidity(
+        program_id,
+        swap_account_address,
+        token_program_address,
+        pool_authority_address,
+        user_transfer_authority_address,
+        swap_token_addresses,
+        pool_token_mint_address,
+        source_token_addresses,
+        lp_token_account_address,
+        deposit_amounts,
+        min_mint_amount,
+    )
+}
+
+/// Accounts of a single LP in a [SwapInstruction::AddLiquidityBatch]
+pub struct BatchDepositAccounts<'a> {
+    pub user_transfer_authority_address: &'a Pubkey,
+    pub source_token_addresses: Vec<&'a Pubkey>,
+    pub lp_token_account_address: &'a Pubkey,
+}
+
+/// Creates a [SwapInstruction::AddLiquidityBatch] instruction, `user_accounts` being in the
+/// same order as `entries`
+#[allow(clippy::too_many_arguments)]
+pub fn add_liquidity_batch(
+    program_id: &Pubkey,
+    swap_account_address: &Pubkey,
+    token_program_address: &Pubkey,
+    pool_authority_address: &Pubkey,
+    swap_token_addresses: Vec<&Pubkey>,
+    pool_token_mint_address: &Pubkey,
+    user_accounts: Vec<BatchDepositAccounts>,
+    entries: Vec<(Vec<u64>, u64)>,
+) -> Result<Instruction, ProgramError> {
+    check_program_account(program_id)?;
+    check_token_program_account(program_id, token_program_address)?;
+    if entries.len() > MAX_BATCH {
+        return Err(SwapError::InvalidInstruction.into());
+    }
+    if user_accounts.len() != entries.len() {
+        return Err(SwapError::ExpectedAccount.into());
+    }
+    if user_accounts
+        .iter()
+        .any(|user| user.source_token_addresses.len() != swap_token_addresses.len())
+    {
+        return Err(SwapError::InvalidNCoins.into());
+    }
+
+    let mut accounts = Vec::with_capacity(
+        PoolParameter::MAX_N_COINS + 4 + entries.len() * (PoolParameter::MAX_N_COINS + 2),
+    );
+    accounts.push(AccountMeta::new_readonly(*swap_account_address, false));
+    accounts.push(AccountMeta::new_readonly(*token_program_address, false));
+    accounts.push(AccountMeta::new_readonly(*pool_authority_address, false));
+    accounts.extend(token_account_metas(&swap_token_addresses, true));
+    accounts.push(AccountMeta::new(*pool_token_mint_address, false));
+    for user in user_accounts {
+        accounts.push(AccountMeta::new_readonly(
+            *user.user_transfer_authority_address,
+            true,
+        ));
+        accounts.extend(token_account_metas(&user.source_token_addresses, true));
+        accounts.push(AccountMeta::new(*user.lp_token_account_address, false));
+    }
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data: SwapInstruction::AddLiquidityBatch { entries }.try_pack()?,
     })
 }
 
 /// Creates a [SwapInstruction::RemoveLiquidity] instruction
+#[allow(clippy::too_many_arguments)]
 pub fn remove_liquidity(
     program_id: &Pubkey,
     swap_account_address: &Pubkey,
@@ -518,6 +1705,15 @@ pub fn remove_liquidity(
     minimum_amounts: Vec<u64>,
 ) -> Result<Instruction, ProgramError> {
     check_program_account(program_id)?;
+    check_token_program_account(program_id, token_program_address)?;
+    if unmint_amount == 0 {
+        return Err(SwapError::ZeroUnmint.into());
+    }
+    if user_destination_token_account_addresses.len() != swap_token_accounts_addresses.len()
+        || minimum_amounts.len() != swap_token_accounts_addresses.len()
+    {
+        return Err(SwapError::InvalidNCoins.into());
+    }
 
     let mut accounts = Vec::with_capacity(PoolParameter::MAX_N_COINS + 5);
     accounts.push(AccountMeta::new_readonly(*swap_account_address, false));
@@ -527,16 +1723,12 @@ pub fn remove_liquidity(
         *user_transfer_authority_address,
         true,
     ));
-    for token_account_address in swap_token_accounts_addresses {
-        accounts.push(AccountMeta::new(*token_account_address, false));
-    }
+    accounts.extend(token_account_metas(&swap_token_accounts_addresses, true));
     accounts.push(AccountMeta::new(*pool_mint_address, false));
-    for user_destination_token_account_address in user_destination_token_account_addresses {
-        accounts.push(AccountMeta::new(
-            *user_destination_token_account_address,
-            false,
-        ));
-    }
+    accounts.extend(token_account_metas(
+        &user_destination_token_account_addresses,
+        true,
+    ));
     accounts.push(AccountMeta::new(*lp_token_account_address, false));
 
     Ok(Instruction {
@@ -546,11 +1738,46 @@ pub fn remove_liquidity(
             unmint_amount,
             minimum_amounts,
         }
-        .pack(),
+        .try_pack()?,
     })
 }
 
+/// Creates a [SwapInstruction::RemoveLiquidity] instruction, taking the swap token accounts and
+/// pool mint from the swap state
+#[allow(clippy::too_many_arguments)]
+pub fn remove_liquidity_from_state(
+    program_id: &Pubkey,
+    swap_account_address: &Pubkey,
+    state: &SwapV2,
+    token_program_address: &Pubkey,
+    pool_authority_address: &Pubkey,
+    user_transfer_authority_address: &Pubkey,
+    user_destination_token_account_addresses: Vec<&Pubkey>,
+    lp_token_account_address: &Pubkey,
+    unmint_amount: u64,
+    minimum_amounts: Vec<u64>,
+) -> Result<Instruction, ProgramError> {
+    if user_destination_token_account_addresses.len() != state.n_coins() {
+        return Err(SwapError::InvalidNCoins.into());
+    }
+
+    remove_liquidity(
+        program_id,
+        swap_account_address,
+        token_program_address,
+        pool_authority_address,
+        user_transfer_authority_address,
+        state.token_account_addresses.iter().collect(),
+        &state.pool_mint_address,
+        user_destination_token_account_addresses,
+        lp_token_account_address,
+        unmint_amount,
+        minimum_amounts,
+    )
+}
+
 /// Creates a [SwapInstruction::RemoveLiquidityOneToken] instruction
+#[allow(clippy::too_many_arguments)]
 pub fn remove_liquidity_one_token(
     program_id: &Pubkey,
     swap_account_address: &Pubkey,
@@ -565,6 +1792,10 @@ pub fn remove_liquidity_one_token(
     minimum_out_amount: u64,
 ) -> Result<Instruction, ProgramError> {
     check_program_account(program_id)?;
+    check_token_program_account(program_id, token_program_address)?;
+    if unmint_amount == 0 {
+        return Err(SwapError::ZeroUnmint.into());
+    }
 
     let mut accounts = Vec::with_capacity(PoolParameter::MAX_N_COINS + 5);
     accounts.push(AccountMeta::new_readonly(*swap_account_address, false));
@@ -574,9 +1805,7 @@ pub fn remove_liquidity_one_token(
         *user_transfer_authority_address,
         true,
     ));
-    for token_account_address in swap_token_accounts_addresses {
-        accounts.push(AccountMeta::new(*token_account_address, false));
-    }
+    accounts.extend(token_account_metas(&swap_token_accounts_addresses, true));
     accounts.push(AccountMeta::new(*pool_mint_address, false));
     accounts.push(AccountMeta::new(
         *user_destination_token_account_address,
@@ -596,6 +1825,7 @@ pub fn remove_liquidity_one_token(
 }
 
 /// Creates a [SwapInstruction::Exchange] instruction
+#[allow(clippy::too_many_arguments)]
 pub fn exchange(
     program_id: &Pubkey,
     swap_account_address: &Pubkey,
@@ -609,6 +1839,13 @@ pub fn exchange(
     minimum_out_amount: u64,
 ) -> Result<Instruction, ProgramError> {
     check_program_account(program_id)?;
+    check_token_program_account(program_id, token_program_address)?;
+    if source_token_account_address == destination_token_account_address {
+        return Err(SwapError::InvalidExchangeAccount.into());
+    }
+    if in_amount == 0 {
+        return Err(SwapError::ZeroAmount.into());
+    }
 
     let mut accounts = Vec::with_capacity(PoolParameter::MAX_N_COINS + 5);
     accounts.push(AccountMeta::new_readonly(*swap_account_address, false));
@@ -618,9 +1855,7 @@ pub fn exchange(
         *user_transfer_authority_address,
         true,
     ));
-    for token_account_address in swap_token_accounts_addresses {
-        accounts.push(AccountMeta::new(*token_account_address, false));
-    }
+    accounts.extend(token_account_metas(&swap_token_accounts_addresses, true));
     accounts.push(AccountMeta::new(*source_token_account_address, false));
     accounts.push(AccountMeta::new(*destination_token_account_address, false));
 
@@ -635,6 +1870,55 @@ pub fn exchange(
     })
 }
 
+/// Creates a [SwapInstruction::ExchangeBps] instruction
+#[allow(clippy::too_many_arguments)]
+pub fn exchange_bps(
+    program_id: &Pubkey,
+    swap_account_address: &Pubkey,
+    token_program_address: &Pubkey,
+    pool_authority_address: &Pubkey,
+    user_transfer_authority_address: &Pubkey,
+    swap_token_accounts_addresses: Vec<&Pubkey>,
+    source_token_account_address: &Pubkey,
+    destination_token_account_address: &Pubkey,
+    in_amount: u64,
+    max_slippage_bps: u16,
+) -> Result<Instruction, ProgramError> {
+    check_program_account(program_id)?;
+    check_token_program_account(program_id, token_program_address)?;
+    if source_token_account_address == destination_token_account_address {
+        return Err(SwapError::InvalidExchangeAccount.into());
+    }
+    if in_amount == 0 {
+        return Err(SwapError::ZeroAmount.into());
+    }
+    if max_slippage_bps > MAX_SLIPPAGE_BPS {
+        return Err(SwapError::InvalidSlippage.into());
+    }
+
+    let mut accounts = Vec::with_capacity(PoolParameter::MAX_N_COINS + 5);
+    accounts.push(AccountMeta::new_readonly(*swap_account_address, false));
+    accounts.push(AccountMeta::new_readonly(*token_program_address, false));
+    accounts.push(AccountMeta::new_readonly(*pool_authority_address, false));
+    accounts.push(AccountMeta::new_readonly(
+        *user_transfer_authority_address,
+        true,
+    ));
+    accounts.extend(token_account_metas(&swap_token_accounts_addresses, true));
+    accounts.push(AccountMeta::new(*source_token_account_address, false));
+    accounts.push(AccountMeta::new(*destination_token_account_address, false));
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data: SwapInstruction::ExchangeBps {
+            in_amount,
+            max_slippage_bps,
+        }
+        .pack(),
+    })
+}
+
 pub fn set_admin_setting(
     program_id: &Pubkey,
     swap_account_address: &Pubkey,
@@ -642,15 +1926,2567 @@ pub fn set_admin_setting(
     nft_owner_address: &Pubkey,
     admin_setting: AdminSetting,
 ) -> Result<Instruction, ProgramError> {
-    check_program_account(&program_id)?;
+    check_program_account(program_id)?;
 
     Ok(Instruction {
         program_id: *program_id,
-        accounts: vec![
-            AccountMeta::new(*swap_account_address, false),
-            AccountMeta::new_readonly(*admin_token_account_address, false),
-            AccountMeta::new_readonly(*nft_owner_address, true),
-        ],
-        data: SwapInstruction::SetAdminSetting { admin_setting }.pack(),
+        accounts: admin_account_metas(
+            swap_account_address,
+            admin_token_account_address,
+            nft_owner_address,
+        ),
+        data: SwapInstruction::SetAdminSetting { admin_setting }.try_pack()?,
     })
 }
+
+/// Creates a [SwapInstruction::Exchange] instruction, reordering the swap token accounts into
+/// the order stored in the swap state
+#[allow(clippy::too_many_arguments)]
+pub fn exchange_in_state_order(
+    program_id: &Pubkey,
+    state: &SwapV2,
+    swap_account_address: &Pubkey,
+    token_program_address: &Pubkey,
+    pool_authority_address: &Pubkey,
+    user_transfer_authority_address: &Pubkey,
+    swap_token_accounts_addresses: Vec<&Pubkey>,
+    source_token_account_address: &Pubkey,
+    destination_token_account_address: &Pubkey,
+    in_amount: u64,
+    minimum_out_amount: u64,
+) -> Result<Instruction, ProgramError> {
+    exchange(
+        program_id,
+        swap_account_address,
+        token_program_address,
+        pool_authority_address,
+        user_transfer_authority_address,
+        order_swap_token_accounts(state, &swap_token_accounts_addresses)?,
+        source_token_account_address,
+        destination_token_account_address,
+        in_amount,
+        minimum_out_amount,
+    )
+}
+
+/// Creates a [SwapInstruction::Exchange] instruction, taking the swap token accounts from the
+/// swap state
+#[allow(clippy::too_many_arguments)]
+pub fn exchange_from_state(
+    program_id: &Pubkey,
+    swap_account_address: &Pubkey,
+    state: &SwapV2,
+    token_program_address: &Pubkey,
+    pool_authority_address: &Pubkey,
+    user_transfer_authority_address: &Pubkey,
+    source_token_account_address: &Pubkey,
+    destination_token_account_address: &Pubkey,
+    in_amount: u64,
+    minimum_out_amount: u64,
+) -> Result<Instruction, ProgramError> {
+    if state.token_account_addresses.iter().any(|address| {
+        address == source_token_account_address || address == destination_token_account_address
+    }) {
+        return Err(SwapError::InvalidExchangeAccount.into());
+    }
+
+    exchange(
+        program_id,
+        swap_account_address,
+        token_program_address,
+        pool_authority_address,
+        user_transfer_authority_address,
+        state.token_account_addresses.iter().collect(),
+        source_token_account_address,
+        destination_token_account_address,
+        in_amount,
+        minimum_out_amount,
+    )
+}
+
+/// Creates a [SwapInstruction::ExchangeWithMemo] instruction
+#[allow(clippy::too_many_arguments)]
+pub fn exchange_with_memo(
+    program_id: &Pubkey,
+    swap_account_address: &Pubkey,
+    token_program_address: &Pubkey,
+    pool_authority_address: &Pubkey,
+    user_transfer_authority_address: &Pubkey,
+    swap_token_accounts_addresses: Vec<&Pubkey>,
+    source_token_account_address: &Pubkey,
+    destination_token_account_address: &Pubkey,
+    in_amount: u64,
+    minimum_out_amount: u64,
+    memo: [u8; 16],
+) -> Result<Instruction, ProgramError> {
+    check_program_account(program_id)?;
+    check_token_program_account(program_id, token_program_address)?;
+    if source_token_account_address == destination_token_account_address {
+        return Err(SwapError::InvalidExchangeAccount.into());
+    }
+    if in_amount == 0 {
+        return Err(SwapError::ZeroAmount.into());
+    }
+
+    let mut accounts = Vec::with_capacity(PoolParameter::MAX_N_COINS + 5);
+    accounts.push(AccountMeta::new_readonly(*swap_account_address, false));
+    accounts.push(AccountMeta::new_readonly(*token_program_address, false));
+    accounts.push(AccountMeta::new_readonly(*pool_authority_address, false));
+    accounts.push(AccountMeta::new_readonly(
+        *user_transfer_authority_address,
+        true,
+    ));
+    accounts.extend(token_account_metas(&swap_token_accounts_addresses, true));
+    accounts.push(AccountMeta::new(*source_token_account_address, false));
+    accounts.push(AccountMeta::new(*destination_token_account_address, false));
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data: SwapInstruction::ExchangeWithMemo {
+            in_amount,
+            minimum_out_amount,
+            memo,
+        }
+        .pack(),
+    })
+}
+
+/// Creates a [SwapInstruction::SetAdminSettings] instruction
+pub fn set_admin_settings(
+    program_id: &Pubkey,
+    swap_account_address: &Pubkey,
+    admin_token_account_address: &Pubkey,
+    nft_owner_address: &Pubkey,
+    settings: AdminSettings,
+) -> Result<Instruction, ProgramError> {
+    check_program_account(program_id)?;
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts: admin_account_metas(
+            swap_account_address,
+            admin_token_account_address,
+            nft_owner_address,
+        ),
+        data: SwapInstruction::SetAdminSettings { settings }.pack(),
+    })
+}
+
+/// Creates a [SwapInstruction::GetVirtualPrice] instruction
+pub fn get_virtual_price(
+    program_id: &Pubkey,
+    swap_account_address: &Pubkey,
+    token_program_address: &Pubkey,
+    swap_token_accounts_addresses: Vec<&Pubkey>,
+    pool_mint_address: &Pubkey,
+) -> Result<Instruction, ProgramError> {
+    check_program_account(program_id)?;
+    check_token_program_account(program_id, token_program_address)?;
+
+    let mut accounts = Vec::with_capacity(PoolParameter::MAX_N_COINS + 3);
+    accounts.push(AccountMeta::new_readonly(*swap_account_address, false));
+    accounts.push(AccountMeta::new_readonly(*token_program_address, false));
+    accounts.extend(token_account_metas(&swap_token_accounts_addresses, false));
+    accounts.push(AccountMeta::new_readonly(*pool_mint_address, false));
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data: SwapInstruction::GetVirtualPrice {}.pack(),
+    })
+}
+
+/// Decodes the virtual price a [SwapInstruction::GetVirtualPrice] sets as return data, a
+/// 16 byte little-endian u128
+pub fn decode_virtual_price_return(data: &[u8]) -> Result<u128, ProgramError> {
+    let data: [u8; 16] = data.try_into().map_err(|_| SwapError::InvalidConversion)?;
+    Ok(u128::from_le_bytes(data))
+}
+
+/// Creates a [SwapInstruction::EmergencyFreeze] instruction
+pub fn emergency_freeze(
+    program_id: &Pubkey,
+    swap_account_address: &Pubkey,
+    admin_token_account_address: &Pubkey,
+    nft_owner_address: &Pubkey,
+) -> Result<Instruction, ProgramError> {
+    check_program_account(program_id)?;
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts: admin_account_metas(
+            swap_account_address,
+            admin_token_account_address,
+            nft_owner_address,
+        ),
+        data: SwapInstruction::EmergencyFreeze {}.pack(),
+    })
+}
+
+/// Creates a [SwapInstruction::CollectProtocolFees] instruction
+#[allow(clippy::too_many_arguments)]
+pub fn collect_protocol_fees(
+    program_id: &Pubkey,
+    swap_account_address: &Pubkey,
+    admin_token_account_address: &Pubkey,
+    nft_owner_address: &Pubkey,
+    token_program_address: &Pubkey,
+    pool_authority_address: &Pubkey,
+    swap_token_accounts_addresses: Vec<&Pubkey>,
+    treasury_token_account_address: &Pubkey,
+) -> Result<Instruction, ProgramError> {
+    check_program_account(program_id)?;
+    check_token_program_account(program_id, token_program_address)?;
+    if swap_token_accounts_addresses.contains(&treasury_token_account_address) {
+        return Err(SwapError::InvalidTokenAccount.into());
+    }
+
+    let mut accounts = admin_account_metas(
+        swap_account_address,
+        admin_token_account_address,
+        nft_owner_address,
+    );
+    accounts.push(AccountMeta::new_readonly(*token_program_address, false));
+    accounts.push(AccountMeta::new_readonly(*pool_authority_address, false));
+    accounts.extend(token_account_metas(&swap_token_accounts_addresses, true));
+    accounts.push(AccountMeta::new(*treasury_token_account_address, false));
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data: SwapInstruction::CollectProtocolFees {}.pack(),
+    })
+}
+
+/// Reorders swap token accounts into the order stored in the swap state, which is the order
+/// every builder expects them in
+pub fn order_swap_token_accounts<'a>(
+    state: &'a SwapV2,
+    swap_token_accounts_addresses: &[&Pubkey],
+) -> Result<Vec<&'a Pubkey>, SwapError> {
+    if swap_token_accounts_addresses.len() != state.token_account_addresses.len() {
+        return Err(SwapError::InvalidTokenAccount);
+    }
+    state
+        .token_account_addresses
+        .iter()
+        .map(|token_account_address| {
+            if swap_token_accounts_addresses.contains(&token_account_address) {
+                Ok(token_account_address)
+            } else {
+                Err(SwapError::InvalidTokenAccount)
+            }
+        })
+        .collect()
+}
+
+/// Lists the accounts shared by every instruction of a pool, in a fixed order suitable for an
+/// address lookup table: swap account, $authority, token accounts in state order, pool mint.
+/// The swap account is not part of the state, so it is passed along with the program id the
+/// $authority is derived with.
+pub fn canonical_pool_accounts(
+    state: &SwapV2,
+    program_id: &Pubkey,
+    swap_account_address: &Pubkey,
+) -> Result<Vec<Pubkey>, ProgramError> {
+    let authority = utils::derive_pool_authority(program_id, swap_account_address, state.nonce)?;
+
+    let mut accounts = Vec::with_capacity(3 + state.n_coins());
+    accounts.push(*swap_account_address);
+    accounts.push(authority);
+    accounts.extend(state.token_account_addresses.iter().copied());
+    accounts.push(state.pool_mint_address);
+    Ok(accounts)
+}
+
+/// Creates a [SwapInstruction::Migrate] instruction, the program funding the realloc to
+/// [SwapVersion::LATEST_LEN] from the payer
+pub fn migrate(
+    program_id: &Pubkey,
+    swap_account_address: &Pubkey,
+    admin_token_account_address: &Pubkey,
+    nft_owner_address: &Pubkey,
+    payer_address: &Pubkey,
+) -> Result<Instruction, ProgramError> {
+    check_program_account(program_id)?;
+
+    let mut accounts = admin_account_metas(
+        swap_account_address,
+        admin_token_account_address,
+        nft_owner_address,
+    );
+    accounts.push(AccountMeta::new(*payer_address, true));
+    accounts.push(AccountMeta::new_readonly(system_program::id(), false));
+    accounts.push(AccountMeta::new_readonly(sysvar::rent::id(), false));
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data: SwapInstruction::Migrate {}.pack(),
+    })
+}
+
+/// Lamports the payer of a [SwapInstruction::Migrate] gets charged for the swap account to
+/// stay rent exempt at [SwapVersion::LATEST_LEN]
+pub fn migration_top_up(rent: &Rent, swap_account_lamports: u64) -> u64 {
+    rent.minimum_balance(SwapVersion::LATEST_LEN)
+        .saturating_sub(swap_account_lamports)
+}
+
+/// Checks that the pool mint is passed to the instruction, writable only if the instruction
+/// mints or burns pool tokens
+pub fn validate_pool_mint_writability(
+    instr: &Instruction,
+    pool_mint_address: &Pubkey,
+    writable: bool,
+) -> Result<(), SwapError> {
+    match instr
+        .accounts
+        .iter()
+        .find(|meta| meta.pubkey == *pool_mint_address)
+    {
+        Some(meta) if meta.is_writable == writable => Ok(()),
+        _ => Err(SwapError::InvalidMint),
+    }
+}
+
+/// Lists the writable accounts of an instruction, e.g. as address lookup table candidates
+pub fn writable_accounts(instr: &Instruction) -> Vec<Pubkey> {
+    instr
+        .accounts
+        .iter()
+        .filter(|meta| meta.is_writable)
+        .map(|meta| meta.pubkey)
+        .collect()
+}
+
+/// Lists the accounts that must sign an instruction
+pub fn required_signers(instr: &Instruction) -> Vec<Pubkey> {
+    instr
+        .accounts
+        .iter()
+        .filter(|meta| meta.is_signer)
+        .map(|meta| meta.pubkey)
+        .collect()
+}
+
+/// Checks that the accounts of a [SwapInstruction::GetVirtualPrice] are complete and pass the
+/// swap token accounts in the order stored in the swap state
+pub fn validate_virtual_price_accounts(
+    metas: &[AccountMeta],
+    state: &SwapV2,
+) -> Result<(), SwapError> {
+    if metas.len() != state.n_coins() + 3 {
+        return Err(SwapError::InvalidTokenAccount);
+    }
+    validate_token_account_metas(metas, 2, state)
+}
+
+/// Checks that no minimum amount of a [SwapInstruction::RemoveLiquidity] exceeds the pool
+/// balance of its coin, which would always fail on slippage
+pub fn validate_minimums(minimum_amounts: &[u64], pool_balances: &[u64]) -> Result<(), SwapError> {
+    if minimum_amounts.len() != pool_balances.len() {
+        return Err(SwapError::InvalidNCoins);
+    }
+    if minimum_amounts
+        .iter()
+        .zip(pool_balances)
+        .any(|(minimum_amount, pool_balance)| minimum_amount > pool_balance)
+    {
+        return Err(SwapError::ExceededSlippage);
+    }
+    Ok(())
+}
+
+/// Checks that the destination of a [SwapInstruction::RemoveLiquidityOneToken] holds one of the
+/// swap coins, given the swap token mints as the swap state does not store them
+pub fn validate_one_token_destination_mint(
+    swap_token_mints: &[Pubkey],
+    destination_mint: &Pubkey,
+) -> Result<(), SwapError> {
+    if !swap_token_mints.contains(destination_mint) {
+        return Err(SwapError::InvalidTokenMatch);
+    }
+    Ok(())
+}
+
+/// Checks the account layout of a [SwapInstruction::Exchange] against the swap state: the
+/// account count, the readonly swap state at swap_account_address, the swap token accounts in
+/// stored order and writable source and destination token accounts
+pub fn validate_exchange_accounts(
+    metas: &[AccountMeta],
+    swap_account_address: &Pubkey,
+    state: &SwapV2,
+) -> Result<(), SwapError> {
+    let n_coins = state.token_account_addresses.len();
+    if metas.len() != n_coins + 6 {
+        return Err(SwapError::InvalidExchangeAccount);
+    }
+    if metas[0].pubkey != *swap_account_address || metas[0].is_writable || metas[0].is_signer {
+        return Err(SwapError::InvalidExchangeAccount);
+    }
+    validate_token_account_metas(metas, 4, state)?;
+    if !metas[n_coins + 4].is_writable || !metas[n_coins + 5].is_writable {
+        return Err(SwapError::InvalidExchangeAccount);
+    }
+    Ok(())
+}
+
+/// Checks an instruction against the swap state before submitting it: per coin vectors must
+/// have one entry per coin, and swaps or deposits must be enabled for the instructions doing them
+pub fn assert_instruction_matches_state(
+    instr: &SwapInstruction,
+    state: &SwapV2,
+) -> Result<(), SwapError> {
+    let n_coins = state.n_coins();
+    let check_n_coins = |len: usize| {
+        if len != n_coins {
+            return Err(SwapError::InvalidNCoins);
+        }
+        Ok(())
+    };
+    let check_add_liquidity_enabled = || {
+        if !state.admin_settings.add_liquidity_enabled {
+            return Err(SwapError::AddLiquidityDisabled);
+        }
+        Ok(())
+    };
+
+    match instr {
+        SwapInstruction::Initialize { .. } => {
+            if state.is_initialized {
+                return Err(SwapError::SwapAlreadyInitialized);
+            }
+        }
+        SwapInstruction::AddLiquidity {
+            deposit_amounts, ..
+        } => {
+            check_add_liquidity_enabled()?;
+            check_n_coins(deposit_amounts.len())?;
+        }
+        SwapInstruction::AddLiquidityBatch { entries } => {
+            check_add_liquidity_enabled()?;
+            for (deposit_amounts, _) in entries.iter() {
+                check_n_coins(deposit_amounts.len())?;
+            }
+        }
+        SwapInstruction::RemoveLiquidity {
+            minimum_amounts, ..
+        } => check_n_coins(minimum_amounts.len())?,
+        SwapInstruction::Exchange { .. }
+        | SwapInstruction::ExchangeWithMemo { .. }
+        | SwapInstruction::ExchangeBps { .. } => {
+            if !state.admin_settings.swap_enabled {
+                return Err(SwapError::SwapDisabled);
+            }
+        }
+        SwapInstruction::SetAdminSetting {
+            admin_setting: AdminSetting::SetPrecisionMultipliers(precision_multipliers),
+        } => check_n_coins(precision_multipliers.len())?,
+        SwapInstruction::RemoveLiquidityOneToken { .. }
+        | SwapInstruction::GetVirtualPrice {}
+        | SwapInstruction::SetAdminSetting { .. }
+        | SwapInstruction::EmergencyFreeze {}
+        | SwapInstruction::Migrate {}
+        | SwapInstruction::SetAdminSettings { .. }
+        | SwapInstruction::CollectProtocolFees {} => {}
+    }
+    Ok(())
+}
+
+/// Checks that the metas starting at `offset` are the swap token accounts in stored order
+fn validate_token_account_metas(
+    metas: &[AccountMeta],
+    offset: usize,
+    state: &SwapV2,
+) -> Result<(), SwapError> {
+    let token_account_metas = metas
+        .get(offset..offset + state.token_account_addresses.len())
+        .ok_or(SwapError::InvalidTokenAccount)?;
+    for (meta, token_account_address) in token_account_metas
+        .iter()
+        .zip(state.token_account_addresses.iter())
+    {
+        if meta.pubkey != *token_account_address {
+            return Err(SwapError::InvalidTokenAccount);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+    use solana_program::hash::hash;
+    use solana_program::program_pack::Pack;
+
+    #[test]
+    fn test_unpack_verbose_reports_truncated_field() {
+        let add_liquidity = SwapInstruction::AddLiquidity {
+            deposit_amounts: vec![1, 2],
+            min_mint_amount: 3,
+        }
+        .pack();
+        let cases = [
+            (0, "tag"),
+            (1, "deposit_amounts"),
+            (5, "deposit_amounts"),
+            (13, "deposit_amounts"),
+            (21, "min_mint_amount"),
+            (28, "min_mint_amount"),
+        ];
+        for &(len, field) in cases.iter() {
+            assert_eq!(
+                SwapInstruction::unpack_verbose(&add_liquidity[..len]),
+                Err((SwapError::InvalidInstruction, field)),
+            );
+        }
+
+        let initialize = SwapInstruction::Initialize {
+            nonce: 1,
+            amplification_coefficient: 100,
+            fee_numerator: 4_000_000,
+            admin_fee_numerator: 0,
+            n_coins: 2,
+            admin_settings: AdminSettings::default(),
+        }
+        .pack();
+        let cases = [
+            (2, "n_coins"),
+            (3, "amplification_coefficient"),
+            (11, "fee_numerator"),
+            (19, "admin_fee_numerator"),
+            (27, "swap_enabled"),
+            (28, "add_liquidity_enabled"),
+        ];
+        for &(len, field) in cases.iter() {
+            assert_eq!(
+                SwapInstruction::unpack_verbose(&initialize[..len]),
+                Err((SwapError::InvalidInstruction, field)),
+            );
+        }
+    }
+
+    #[test]
+    fn test_unpack_verbose_truncated_memo() {
+        let exchange_with_memo = SwapInstruction::ExchangeWithMemo {
+            in_amount: 1,
+            minimum_out_amount: 2,
+            memo: [7; 16],
+        }
+        .pack();
+        for len in 17..exchange_with_memo.len() {
+            assert_eq!(
+                SwapInstruction::unpack_verbose(&exchange_with_memo[..len]),
+                Err((SwapError::InvalidInstruction, "memo")),
+            );
+        }
+    }
+
+    #[test]
+    fn test_unpack_verbose_reports_swap_error() {
+        let exchange_bps = SwapInstruction::ExchangeBps {
+            in_amount: 1,
+            max_slippage_bps: MAX_SLIPPAGE_BPS + 1,
+        }
+        .pack();
+        assert_eq!(
+            SwapInstruction::unpack_verbose(&exchange_bps),
+            Err((SwapError::InvalidSlippage, "max_slippage_bps")),
+        );
+        assert_eq!(
+            SwapInstruction::unpack_verbose(&[99]),
+            Err((SwapError::InvalidInstruction, "tag")),
+        );
+    }
+
+    fn concat(instructions: &[SwapInstruction]) -> Vec<u8> {
+        instructions
+            .iter()
+            .flat_map(|instruction| instruction.pack())
+            .collect()
+    }
+
+    #[test]
+    fn test_unpack_all_two_instructions() {
+        let instructions = vec![
+            SwapInstruction::ExchangeWithMemo {
+                in_amount: 10,
+                minimum_out_amount: 9,
+                memo: [1; 16],
+            },
+            SwapInstruction::GetVirtualPrice {},
+        ];
+        assert_eq!(
+            SwapInstruction::unpack_all(&concat(&instructions)),
+            Ok(instructions)
+        );
+    }
+
+    #[test]
+    fn test_unpack_all_three_instructions() {
+        let instructions = vec![
+            SwapInstruction::AddLiquidity {
+                deposit_amounts: vec![1, 2, 3],
+                min_mint_amount: 4,
+            },
+            SwapInstruction::ExchangeWithMemo {
+                in_amount: 10,
+                minimum_out_amount: 9,
+                memo: [0; 16],
+            },
+            SwapInstruction::RemoveLiquidity {
+                unmint_amount: 5,
+                minimum_amounts: vec![1, 1],
+            },
+        ];
+        assert_eq!(
+            SwapInstruction::unpack_all(&concat(&instructions)),
+            Ok(instructions)
+        );
+    }
+
+    #[test]
+    fn test_unpack_all_trailing_garbage() {
+        let mut data = concat(&[
+            SwapInstruction::GetVirtualPrice {},
+            SwapInstruction::Exchange {
+                in_amount: 10,
+                minimum_out_amount: 9,
+            },
+        ]);
+        data.push(4);
+        assert_eq!(
+            SwapInstruction::unpack_all(&data),
+            Err(SwapError::InvalidInstruction.into())
+        );
+    }
+
+    #[test]
+    fn test_unpack_memo_must_end_input() {
+        let mut data = SwapInstruction::ExchangeWithMemo {
+            in_amount: 10,
+            minimum_out_amount: 9,
+            memo: [1; 16],
+        }
+        .pack();
+        data.push(0);
+        assert_eq!(
+            SwapInstruction::unpack(&data),
+            Err(SwapError::InvalidInstruction.into())
+        );
+    }
+
+    /// Amount vectors of up to [PoolParameter::MAX_N_COINS] coins, shrinking towards fewer and
+    /// smaller amounts
+    fn amounts() -> impl Strategy<Value = Vec<u64>> {
+        prop::collection::vec(any::<u64>(), 0..=PoolParameter::MAX_N_COINS)
+    }
+
+    fn admin_settings() -> impl Strategy<Value = AdminSettings> {
+        (any::<bool>(), any::<bool>()).prop_map(|(swap_enabled, add_liquidity_enabled)| {
+            AdminSettings {
+                swap_enabled,
+                add_liquidity_enabled,
+            }
+        })
+    }
+
+    fn admin_setting() -> impl Strategy<Value = AdminSetting> {
+        prop_oneof![
+            any::<bool>().prop_map(AdminSetting::SetSwapEnabled),
+            any::<bool>().prop_map(AdminSetting::SetAddLiquidityEnabled),
+            any::<u64>().prop_map(AdminSetting::SetAmplificationCoefficient),
+            any::<u64>().prop_map(AdminSetting::SetFeeNumerator),
+            any::<u64>().prop_map(AdminSetting::SetAdminFeeNumerator),
+            amounts().prop_map(AdminSetting::SetPrecisionMultipliers),
+        ]
+    }
+
+    fn swap_instruction() -> impl Strategy<Value = SwapInstruction> {
+        prop_oneof![
+            (
+                any::<u8>(),
+                any::<u64>(),
+                any::<u64>(),
+                any::<u64>(),
+                any::<u8>(),
+                admin_settings()
+            )
+                .prop_map(
+                    |(
+                        nonce,
+                        amplification_coefficient,
+                        fee_numerator,
+                        admin_fee_numerator,
+                        n_coins,
+                        admin_settings,
+                    )| SwapInstruction::Initialize {
+                        nonce,
+                        amplification_coefficient,
+                        fee_numerator,
+                        admin_fee_numerator,
+                        n_coins,
+                        admin_settings,
+                    }
+                ),
+            (amounts(), any::<u64>()).prop_map(|(deposit_amounts, min_mint_amount)| {
+                SwapInstruction::AddLiquidity {
+                    deposit_amounts,
+                    min_mint_amount,
+                }
+            }),
+            (any::<u64>(), amounts()).prop_map(|(unmint_amount, minimum_amounts)| {
+                SwapInstruction::RemoveLiquidity {
+                    unmint_amount,
+                    minimum_amounts,
+                }
+            }),
+            (any::<u64>(), any::<u64>()).prop_map(|(unmint_amount, minimum_out_amount)| {
+                SwapInstruction::RemoveLiquidityOneToken {
+                    unmint_amount,
+                    minimum_out_amount,
+                }
+            }),
+            (any::<u64>(), any::<u64>()).prop_map(|(in_amount, minimum_out_amount)| {
+                SwapInstruction::Exchange {
+                    in_amount,
+                    minimum_out_amount,
+                }
+            }),
+            Just(SwapInstruction::GetVirtualPrice {}),
+            admin_setting()
+                .prop_map(|admin_setting| SwapInstruction::SetAdminSetting { admin_setting }),
+            Just(SwapInstruction::EmergencyFreeze {}),
+            (any::<u64>(), any::<u64>(), any::<[u8; 16]>()).prop_map(
+                |(in_amount, minimum_out_amount, memo)| SwapInstruction::ExchangeWithMemo {
+                    in_amount,
+                    minimum_out_amount,
+                    memo,
+                }
+            ),
+            prop::collection::vec((amounts(), any::<u64>()), 0..=MAX_BATCH)
+                .prop_map(|entries| SwapInstruction::AddLiquidityBatch { entries }),
+            Just(SwapInstruction::Migrate {}),
+            admin_settings().prop_map(|settings| SwapInstruction::SetAdminSettings { settings }),
+            Just(SwapInstruction::CollectProtocolFees {}),
+            (any::<u64>(), 0..=MAX_SLIPPAGE_BPS).prop_map(|(in_amount, max_slippage_bps)| {
+                SwapInstruction::ExchangeBps {
+                    in_amount,
+                    max_slippage_bps,
+                }
+            }),
+        ]
+    }
+
+    proptest! {
+        #[test]
+        fn test_pack_unpack_round_trip(instruction in swap_instruction()) {
+            prop_assert_eq!(SwapInstruction::unpack(&instruction.pack())?, instruction);
+        }
+
+        #[test]
+        fn test_expected_len_matches_pack_for_any_instruction(instruction in swap_instruction()) {
+            prop_assert_eq!(instruction.expected_len(), instruction.pack().len());
+        }
+    }
+
+    #[test]
+    fn test_canonical_pool_accounts_is_deterministic() {
+        let swap_account = Pubkey::new_unique();
+        let pdas = utils::pool_pdas(&crate::id(), &swap_account);
+        let state = SwapV2 {
+            nonce: pdas.nonce,
+            ..three_coin_state()
+        };
+
+        let accounts = canonical_pool_accounts(&state, &crate::id(), &swap_account).unwrap();
+        assert_eq!(
+            accounts,
+            vec![
+                swap_account,
+                pdas.authority,
+                state.token_account_addresses[0],
+                state.token_account_addresses[1],
+                state.token_account_addresses[2],
+                state.pool_mint_address,
+            ]
+        );
+        for _ in 0..3 {
+            assert_eq!(
+                canonical_pool_accounts(&state, &crate::id(), &swap_account),
+                Ok(accounts.clone())
+            );
+        }
+    }
+
+    /// One instance of every variant, and of every [AdminSetting]
+    fn every_variant() -> Vec<SwapInstruction> {
+        vec![
+            SwapInstruction::Initialize {
+                nonce: 1,
+                amplification_coefficient: 100,
+                fee_numerator: 4_000_000,
+                admin_fee_numerator: 0,
+                n_coins: 3,
+                admin_settings: AdminSettings::default(),
+            },
+            SwapInstruction::AddLiquidity {
+                deposit_amounts: vec![1, 2, 3],
+                min_mint_amount: 4,
+            },
+            SwapInstruction::RemoveLiquidity {
+                unmint_amount: 5,
+                minimum_amounts: vec![1, 2, 3, 4],
+            },
+            SwapInstruction::RemoveLiquidityOneToken {
+                unmint_amount: 5,
+                minimum_out_amount: 4,
+            },
+            SwapInstruction::Exchange {
+                in_amount: 10,
+                minimum_out_amount: 9,
+            },
+            SwapInstruction::GetVirtualPrice {},
+            SwapInstruction::SetAdminSetting {
+                admin_setting: AdminSetting::SetSwapEnabled(true),
+            },
+            SwapInstruction::SetAdminSetting {
+                admin_setting: AdminSetting::SetAddLiquidityEnabled(false),
+            },
+            SwapInstruction::SetAdminSetting {
+                admin_setting: AdminSetting::SetAmplificationCoefficient(200),
+            },
+            SwapInstruction::SetAdminSetting {
+                admin_setting: AdminSetting::SetFeeNumerator(1),
+            },
+            SwapInstruction::SetAdminSetting {
+                admin_setting: AdminSetting::SetAdminFeeNumerator(2),
+            },
+            SwapInstruction::SetAdminSetting {
+                admin_setting: AdminSetting::SetPrecisionMultipliers(vec![1, 1_000]),
+            },
+            SwapInstruction::EmergencyFreeze {},
+            SwapInstruction::ExchangeWithMemo {
+                in_amount: 10,
+                minimum_out_amount: 9,
+                memo: [3; 16],
+            },
+            SwapInstruction::AddLiquidityBatch {
+                entries: vec![(vec![1, 2], 3), (vec![], 0), (vec![4, 5, 6, 7], 8)],
+            },
+            SwapInstruction::Migrate {},
+            SwapInstruction::SetAdminSettings {
+                settings: AdminSettings {
+                    swap_enabled: true,
+                    add_liquidity_enabled: false,
+                },
+            },
+            SwapInstruction::CollectProtocolFees {},
+            SwapInstruction::ExchangeBps {
+                in_amount: 10,
+                max_slippage_bps: 50,
+            },
+        ]
+    }
+
+    #[test]
+    fn test_every_variant_covers_every_tag() {
+        let mut tags: Vec<u8> = every_variant()
+            .iter()
+            .map(|instruction| instruction.pack()[0])
+            .collect();
+        tags.sort_unstable();
+        tags.dedup();
+        let known_tags: Vec<u8> = (0..=u8::MAX)
+            .filter(|&tag| SwapInstructionTag::from_u8(tag).is_some())
+            .collect();
+        assert_eq!(tags, known_tags);
+    }
+
+    #[test]
+    fn test_expected_len_matches_pack() {
+        for instruction in every_variant() {
+            assert_eq!(
+                instruction.expected_len(),
+                instruction.pack().len(),
+                "{:?}",
+                instruction
+            );
+        }
+    }
+
+    fn three_coin_state() -> SwapV2 {
+        SwapV2 {
+            is_initialized: true,
+            token_account_addresses: vec![
+                Pubkey::new_unique(),
+                Pubkey::new_unique(),
+                Pubkey::new_unique(),
+            ],
+            pool_mint_address: Pubkey::new_unique(),
+            ..SwapV2::default()
+        }
+    }
+
+    fn virtual_price_metas(state: &SwapV2) -> Vec<AccountMeta> {
+        get_virtual_price(
+            &crate::id(),
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+            state.token_account_addresses.iter().collect(),
+            &state.pool_mint_address,
+        )
+        .unwrap()
+        .accounts
+    }
+
+    #[test]
+    fn test_validate_virtual_price_accounts_in_order() {
+        let state = three_coin_state();
+        assert_eq!(
+            validate_virtual_price_accounts(&virtual_price_metas(&state), &state),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn test_validate_virtual_price_accounts_wrong_order() {
+        let state = three_coin_state();
+        let mut metas = virtual_price_metas(&state);
+        metas.swap(2, 3);
+        assert_eq!(
+            validate_virtual_price_accounts(&metas, &state),
+            Err(SwapError::InvalidTokenAccount)
+        );
+    }
+
+    #[test]
+    fn test_validate_virtual_price_accounts_missing_or_extra_account() {
+        let state = three_coin_state();
+        let metas = virtual_price_metas(&state);
+        assert_eq!(
+            validate_virtual_price_accounts(&metas[..metas.len() - 1], &state),
+            Err(SwapError::InvalidTokenAccount)
+        );
+
+        let mut extra = metas;
+        extra.push(AccountMeta::new_readonly(Pubkey::new_unique(), false));
+        assert_eq!(
+            validate_virtual_price_accounts(&extra, &state),
+            Err(SwapError::InvalidTokenAccount)
+        );
+    }
+
+    fn add_liquidity_data(
+        padded: bool,
+        deposit_amounts: Vec<u64>,
+    ) -> Result<Vec<u8>, ProgramError> {
+        let swap_token_accounts = [
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+        ];
+        let source_token_accounts = [
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+        ];
+        let builder = if padded {
+            add_liquidity_padded
+        } else {
+            add_liquidity
+        };
+        builder(
+            &crate::id(),
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+            swap_token_accounts.iter().collect(),
+            &Pubkey::new_unique(),
+            source_token_accounts.iter().collect(),
+            &Pubkey::new_unique(),
+            deposit_amounts,
+            7,
+        )
+        .map(|instruction| instruction.data)
+    }
+
+    #[test]
+    fn test_add_liquidity_keeps_deposit_amounts() {
+        assert_eq!(
+            SwapInstruction::unpack(&add_liquidity_data(false, vec![1, 2]).unwrap()),
+            Ok(SwapInstruction::AddLiquidity {
+                deposit_amounts: vec![1, 2],
+                min_mint_amount: 7,
+            })
+        );
+    }
+
+    #[test]
+    fn test_add_liquidity_padded() {
+        assert_eq!(
+            SwapInstruction::unpack(&add_liquidity_data(true, vec![1, 2]).unwrap()),
+            Ok(SwapInstruction::AddLiquidity {
+                deposit_amounts: vec![1, 2, 0],
+                min_mint_amount: 7,
+            })
+        );
+        assert_eq!(
+            add_liquidity_data(true, vec![1, 2, 3]),
+            add_liquidity_data(false, vec![1, 2, 3])
+        );
+        assert_eq!(
+            add_liquidity_data(true, vec![1, 2, 3, 4]),
+            Err(SwapError::InvalidNCoins.into())
+        );
+    }
+
+    #[test]
+    fn test_peek_tag() {
+        let tags = [
+            (0, SwapInstructionTag::Initialize),
+            (1, SwapInstructionTag::AddLiquidity),
+            (2, SwapInstructionTag::RemoveLiquidity),
+            (3, SwapInstructionTag::RemoveLiquidityOneToken),
+            (4, SwapInstructionTag::Exchange),
+            (5, SwapInstructionTag::GetVirtualPrice),
+            (24, SwapInstructionTag::EmergencyFreeze),
+            (25, SwapInstructionTag::ExchangeWithMemo),
+            (26, SwapInstructionTag::AddLiquidityBatch),
+            (27, SwapInstructionTag::Migrate),
+            (28, SwapInstructionTag::SetAdminSettings),
+            (29, SwapInstructionTag::CollectProtocolFees),
+            (30, SwapInstructionTag::ExchangeBps),
+            (100, SwapInstructionTag::SetSwapEnabled),
+            (101, SwapInstructionTag::SetAddLiquidityEnabled),
+            (102, SwapInstructionTag::SetAmplificationCoefficient),
+            (103, SwapInstructionTag::SetFeeNumerator),
+            (104, SwapInstructionTag::SetAdminFeeNumerator),
+            (105, SwapInstructionTag::SetPrecisionMultipliers),
+        ];
+        for (byte, tag) in tags {
+            // only the tag is read, the payload may be anything
+            assert_eq!(SwapInstruction::peek_tag(&[byte]), Ok(tag));
+            assert_eq!(SwapInstruction::peek_tag(&[byte, 0xff, 0xff]), Ok(tag));
+        }
+    }
+
+    #[test]
+    fn test_peek_tag_unknown() {
+        for byte in [6, 23, 31, 99, 106, 255] {
+            assert_eq!(
+                SwapInstruction::peek_tag(&[byte]),
+                Err(SwapError::InvalidInstruction.into())
+            );
+        }
+        assert_eq!(
+            SwapInstruction::peek_tag(&[]),
+            Err(SwapError::InvalidInstruction.into())
+        );
+    }
+
+    #[test]
+    fn test_create_and_initialize() {
+        let payer = Pubkey::new_unique();
+        let swap_account = Pubkey::new_unique();
+        let token_accounts = [Pubkey::new_unique(), Pubkey::new_unique()];
+        let token_mints = [Pubkey::new_unique(), Pubkey::new_unique()];
+        let [create_account, initialize] = create_and_initialize(
+            &crate::id(),
+            &payer,
+            &swap_account,
+            1_000,
+            &Pubkey::new_unique(),
+            token_accounts.iter().collect(),
+            token_mints.iter().collect(),
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+            255,
+            2,
+            100,
+            4,
+            5,
+            AdminSettings::default(),
+        )
+        .unwrap();
+
+        assert_eq!(
+            create_account.program_id,
+            solana_program::system_program::id()
+        );
+        assert_eq!(create_account.accounts[0].pubkey, payer);
+        assert_eq!(create_account.accounts[1].pubkey, swap_account);
+        // bincode CreateAccount { lamports, space, owner } after the u32 discriminant
+        let data = &create_account.data;
+        assert_eq!(data[..4], 0u32.to_le_bytes());
+        assert_eq!(data[4..12], 1_000u64.to_le_bytes());
+        assert_eq!(data[12..20], (SwapVersion::LATEST_LEN as u64).to_le_bytes());
+        assert_eq!(data[20..52], crate::id().to_bytes());
+
+        assert_eq!(initialize.program_id, crate::id());
+        assert_eq!(
+            SwapInstruction::peek_tag(&initialize.data),
+            Ok(SwapInstructionTag::Initialize)
+        );
+    }
+
+    #[test]
+    fn test_emergency_freeze_round_trip() {
+        let instruction = emergency_freeze(
+            &crate::id(),
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+        )
+        .unwrap();
+        assert_eq!(instruction.data, vec![24]);
+        assert_eq!(
+            SwapInstruction::unpack(&instruction.data),
+            Ok(SwapInstruction::EmergencyFreeze {})
+        );
+    }
+
+    #[test]
+    fn test_anchor_round_trip() {
+        for instruction in every_variant() {
+            let packed = instruction.pack_anchor();
+            assert_eq!(packed[..8], instruction.anchor_discriminator());
+            assert_eq!(packed[8..], instruction.pack());
+            assert_eq!(SwapInstruction::unpack_anchor(&packed), Ok(instruction));
+        }
+    }
+
+    #[test]
+    fn test_anchor_discriminators_table() {
+        for instruction in every_variant() {
+            assert_eq!(
+                Ok(instruction.tag()),
+                SwapInstruction::peek_tag(&instruction.pack())
+            );
+            let name = match instruction.tag() {
+                SwapInstructionTag::SetSwapEnabled
+                | SwapInstructionTag::SetAddLiquidityEnabled
+                | SwapInstructionTag::SetAmplificationCoefficient
+                | SwapInstructionTag::SetFeeNumerator
+                | SwapInstructionTag::SetAdminFeeNumerator
+                | SwapInstructionTag::SetPrecisionMultipliers => "set_admin_setting".to_string(),
+                tag => {
+                    // snake case of the tag name
+                    let mut name = String::new();
+                    for c in format!("{:?}", tag).chars() {
+                        if c.is_uppercase() && !name.is_empty() {
+                            name.push('_');
+                        }
+                        name.push(c.to_ascii_lowercase());
+                    }
+                    name
+                }
+            };
+            assert_eq!(
+                instruction.anchor_discriminator()[..],
+                hash(format!("global:{}", name).as_bytes()).to_bytes()[..8]
+            );
+        }
+    }
+
+    #[test]
+    fn test_unpack_anchor_rejects_wrong_discriminator() {
+        let mut packed = SwapInstruction::GetVirtualPrice {}.pack_anchor();
+        packed[..8].copy_from_slice(&SwapInstruction::EmergencyFreeze {}.anchor_discriminator());
+        assert_eq!(
+            SwapInstruction::unpack_anchor(&packed),
+            Err(SwapError::InvalidInstruction.into())
+        );
+        assert_eq!(
+            SwapInstruction::unpack_anchor(&packed[..7]),
+            Err(SwapError::InvalidInstruction.into())
+        );
+    }
+
+    fn remove_liquidity_with(
+        token_program_address: &Pubkey,
+        unmint_amount: u64,
+    ) -> Result<Instruction, ProgramError> {
+        let swap_token_accounts = [Pubkey::new_unique(), Pubkey::new_unique()];
+        let destination_token_accounts = [Pubkey::new_unique(), Pubkey::new_unique()];
+        remove_liquidity(
+            &crate::id(),
+            &Pubkey::new_unique(),
+            token_program_address,
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+            swap_token_accounts.iter().collect(),
+            &Pubkey::new_unique(),
+            destination_token_accounts.iter().collect(),
+            &Pubkey::new_unique(),
+            unmint_amount,
+            vec![1, 2],
+        )
+    }
+
+    fn remove_liquidity_one_token_with(
+        token_program_address: &Pubkey,
+        unmint_amount: u64,
+    ) -> Result<Instruction, ProgramError> {
+        let swap_token_accounts = [Pubkey::new_unique(), Pubkey::new_unique()];
+        remove_liquidity_one_token(
+            &crate::id(),
+            &Pubkey::new_unique(),
+            token_program_address,
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+            swap_token_accounts.iter().collect(),
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+            unmint_amount,
+            1,
+        )
+    }
+
+    #[test]
+    fn test_remove_liquidity_zero_unmint() {
+        let token_program = Pubkey::new_unique();
+        assert_eq!(
+            remove_liquidity_with(&token_program, 0),
+            Err(SwapError::ZeroUnmint.into())
+        );
+        assert_eq!(
+            remove_liquidity_one_token_with(&token_program, 0),
+            Err(SwapError::ZeroUnmint.into())
+        );
+    }
+
+    #[test]
+    fn test_remove_liquidity_non_zero_unmint() {
+        let token_program = Pubkey::new_unique();
+        let instruction = remove_liquidity_with(&token_program, 10).unwrap();
+        assert_eq!(
+            SwapInstruction::unpack(&instruction.data),
+            Ok(SwapInstruction::RemoveLiquidity {
+                unmint_amount: 10,
+                minimum_amounts: vec![1, 2],
+            })
+        );
+        let instruction = remove_liquidity_one_token_with(&token_program, 10).unwrap();
+        assert_eq!(
+            SwapInstruction::unpack(&instruction.data),
+            Ok(SwapInstruction::RemoveLiquidityOneToken {
+                unmint_amount: 10,
+                minimum_out_amount: 1,
+            })
+        );
+    }
+
+    #[test]
+    fn test_token_account_metas() {
+        let accounts = [Pubkey::new_unique(), Pubkey::new_unique()];
+        let accounts: Vec<&Pubkey> = accounts.iter().collect();
+
+        let writable = token_account_metas(&accounts, true);
+        let readonly = token_account_metas(&accounts, false);
+        for (metas, is_writable) in [(writable, true), (readonly, false)] {
+            assert_eq!(metas.len(), accounts.len());
+            for (meta, account) in metas.iter().zip(&accounts) {
+                assert_eq!(meta.pubkey, **account);
+                assert_eq!(meta.is_writable, is_writable);
+                assert!(!meta.is_signer);
+            }
+        }
+        assert!(token_account_metas(&[], true).is_empty());
+    }
+
+    fn initialize_instruction() -> SwapInstruction {
+        SwapInstruction::Initialize {
+            nonce: 254,
+            n_coins: 3,
+            amplification_coefficient: 100,
+            fee_numerator: 4_000_000,
+            admin_fee_numerator: 5_000_000_000,
+            admin_settings: AdminSettings {
+                swap_enabled: true,
+                add_liquidity_enabled: false,
+            },
+        }
+    }
+
+    #[test]
+    fn test_initialize_one_byte_short() {
+        let instruction = initialize_instruction();
+        let packed = instruction.pack();
+        assert_eq!(SwapInstruction::unpack(&packed), Ok(instruction));
+        // the first admin flag is present, the second is missing
+        assert_eq!(
+            SwapInstruction::unpack(&packed[..packed.len() - 1]),
+            Err(SwapError::InvalidInstruction.into())
+        );
+    }
+
+    #[test]
+    fn test_add_liquidity_batch_one_entry() {
+        let instruction = SwapInstruction::AddLiquidityBatch {
+            entries: vec![(vec![1, 2], 3)],
+        };
+        let packed = instruction.try_pack().unwrap();
+        let mut expected = vec![26];
+        expected.extend_from_slice(&1u32.to_le_bytes());
+        expected.extend_from_slice(&2u32.to_le_bytes());
+        expected.extend_from_slice(&1u64.to_le_bytes());
+        expected.extend_from_slice(&2u64.to_le_bytes());
+        expected.extend_from_slice(&3u64.to_le_bytes());
+        assert_eq!(packed, expected);
+        assert_eq!(SwapInstruction::unpack(&packed), Ok(instruction));
+    }
+
+    #[test]
+    fn test_add_liquidity_batch_three_entries() {
+        let instruction = SwapInstruction::AddLiquidityBatch {
+            entries: vec![(vec![1, 2], 3), (vec![4, 5, 6], 7), (vec![], 8)],
+        };
+        let packed = instruction.try_pack().unwrap();
+        assert_eq!(packed.len(), instruction.expected_len());
+        assert_eq!(SwapInstruction::unpack(&packed), Ok(instruction));
+    }
+
+    #[test]
+    fn test_add_liquidity_batch_too_many_entries() {
+        let instruction = SwapInstruction::AddLiquidityBatch {
+            entries: vec![(vec![1], 1); MAX_BATCH + 1],
+        };
+        assert_eq!(
+            instruction.try_pack(),
+            Err(SwapError::InvalidInstruction.into())
+        );
+        assert_eq!(
+            SwapInstruction::unpack(&instruction.pack()),
+            Err(SwapError::InvalidInstruction.into())
+        );
+    }
+
+    #[test]
+    fn test_swap_program_as_token_program() {
+        assert_eq!(
+            remove_liquidity_with(&crate::id(), 10),
+            Err(SwapError::IncorrectTokenProgramId.into())
+        );
+        assert_eq!(
+            remove_liquidity_one_token_with(&crate::id(), 10),
+            Err(SwapError::IncorrectTokenProgramId.into())
+        );
+    }
+
+    fn exchange_with(state: &SwapV2, in_amount: u64) -> Result<Instruction, ProgramError> {
+        exchange(
+            &crate::id(),
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+            state.token_account_addresses.iter().collect(),
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+            in_amount,
+            1,
+        )
+    }
+
+    #[test]
+    fn test_validate_exchange_accounts() {
+        let state = three_coin_state();
+        let metas = exchange_with(&state, 10).unwrap().accounts;
+        assert_eq!(
+            validate_exchange_accounts(&metas, &metas[0].pubkey, &state),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn test_validate_exchange_accounts_broken_layouts() {
+        let state = three_coin_state();
+        let metas = exchange_with(&state, 10).unwrap().accounts;
+        let swap_account = metas[0].pubkey;
+        let n_coins = state.n_coins();
+
+        // missing destination account
+        assert_eq!(
+            validate_exchange_accounts(&metas[..metas.len() - 1], &swap_account, &state),
+            Err(SwapError::InvalidExchangeAccount)
+        );
+
+        // foreign swap state
+        assert_eq!(
+            validate_exchange_accounts(&metas, &Pubkey::new_unique(), &state),
+            Err(SwapError::InvalidExchangeAccount)
+        );
+
+        // writable swap state
+        let mut broken = metas.clone();
+        broken[0].is_writable = true;
+        assert_eq!(
+            validate_exchange_accounts(&broken, &swap_account, &state),
+            Err(SwapError::InvalidExchangeAccount)
+        );
+
+        // swap token accounts out of order
+        let mut broken = metas.clone();
+        broken.swap(4, 5);
+        assert_eq!(
+            validate_exchange_accounts(&broken, &swap_account, &state),
+            Err(SwapError::InvalidTokenAccount)
+        );
+
+        // foreign swap token account
+        let mut broken = metas.clone();
+        broken[4 + n_coins - 1].pubkey = Pubkey::new_unique();
+        assert_eq!(
+            validate_exchange_accounts(&broken, &swap_account, &state),
+            Err(SwapError::InvalidTokenAccount)
+        );
+
+        // readonly source or destination
+        for index in [n_coins + 4, n_coins + 5] {
+            let mut broken = metas.clone();
+            broken[index].is_writable = false;
+            assert_eq!(
+                validate_exchange_accounts(&broken, &swap_account, &state),
+                Err(SwapError::InvalidExchangeAccount)
+            );
+        }
+    }
+
+    #[test]
+    fn test_try_pack_boundary() {
+        let at_max = vec![1; PoolParameter::MAX_N_COINS];
+        let over_max = vec![1; PoolParameter::MAX_N_COINS + 1];
+        let build = |amounts: Vec<u64>| {
+            [
+                SwapInstruction::AddLiquidity {
+                    deposit_amounts: amounts.clone(),
+                    min_mint_amount: 1,
+                },
+                SwapInstruction::RemoveLiquidity {
+                    unmint_amount: 1,
+                    minimum_amounts: amounts.clone(),
+                },
+                SwapInstruction::SetAdminSetting {
+                    admin_setting: AdminSetting::SetPrecisionMultipliers(amounts.clone()),
+                },
+                SwapInstruction::AddLiquidityBatch {
+                    entries: vec![(amounts, 1)],
+                },
+            ]
+        };
+
+        for instruction in build(at_max) {
+            assert_eq!(instruction.try_pack(), Ok(instruction.pack()));
+        }
+        for instruction in build(over_max) {
+            assert_eq!(instruction.try_pack(), Err(SwapError::InvalidNCoins.into()));
+        }
+    }
+
+    fn remove_liquidity_data(length: u32, amounts: &[u64]) -> Vec<u8> {
+        let mut data = vec![2];
+        data.extend_from_slice(&10u64.to_le_bytes());
+        data.extend_from_slice(&length.to_le_bytes());
+        for amount in amounts {
+            data.extend_from_slice(&amount.to_le_bytes());
+        }
+        data
+    }
+
+    #[test]
+    fn test_remove_liquidity_minimum_amounts_length() {
+        let amounts = vec![1; PoolParameter::MAX_N_COINS];
+        assert_eq!(
+            SwapInstruction::unpack(&remove_liquidity_data(amounts.len() as u32, &amounts)),
+            Ok(SwapInstruction::RemoveLiquidity {
+                unmint_amount: 10,
+                minimum_amounts: amounts,
+            })
+        );
+
+        let amounts = vec![1; PoolParameter::MAX_N_COINS + 1];
+        assert_eq!(
+            SwapInstruction::unpack(&remove_liquidity_data(amounts.len() as u32, &amounts)),
+            Err(SwapError::InvalidInstruction.into())
+        );
+        // a huge length is rejected before reading or allocating any amount
+        assert_eq!(
+            SwapInstruction::unpack(&remove_liquidity_data(u32::MAX, &[])),
+            Err(SwapError::InvalidInstruction.into())
+        );
+    }
+
+    #[test]
+    fn test_unpack_empty_input() {
+        assert_eq!(
+            SwapInstruction::unpack(&[]),
+            Err(SwapError::InvalidInstruction.into())
+        );
+        assert_eq!(
+            SwapInstruction::unpack_with_len(&[]),
+            Err(SwapError::InvalidInstruction.into())
+        );
+    }
+
+    fn every_admin_settings() -> [AdminSettings; 4] {
+        [(false, false), (false, true), (true, false), (true, true)].map(
+            |(swap_enabled, add_liquidity_enabled)| AdminSettings {
+                swap_enabled,
+                add_liquidity_enabled,
+            },
+        )
+    }
+
+    #[test]
+    fn test_set_admin_settings_round_trip() {
+        for settings in every_admin_settings() {
+            let instruction = set_admin_settings(
+                &crate::id(),
+                &Pubkey::new_unique(),
+                &Pubkey::new_unique(),
+                &Pubkey::new_unique(),
+                settings,
+            )
+            .unwrap();
+            let mut expected = vec![28];
+            expected.extend_from_slice(&settings.to_bytes());
+            assert_eq!(instruction.data, expected);
+            assert_eq!(
+                SwapInstruction::unpack(&instruction.data),
+                Ok(SwapInstruction::SetAdminSettings { settings })
+            );
+        }
+    }
+
+    fn initialize_with(
+        token_accounts: &[Pubkey],
+        token_mints: &[Pubkey],
+        n_coins: u8,
+    ) -> Result<Instruction, ProgramError> {
+        initialize(
+            &crate::id(),
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+            token_accounts.iter().collect(),
+            token_mints.iter().collect(),
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+            255,
+            n_coins,
+            100,
+            4_000_000,
+            0,
+            AdminSettings::default(),
+        )
+    }
+
+    #[test]
+    fn test_initialize_mismatched_account_counts() {
+        let accounts: Vec<Pubkey> = (0..3).map(|_| Pubkey::new_unique()).collect();
+        let mints: Vec<Pubkey> = (0..3).map(|_| Pubkey::new_unique()).collect();
+        assert!(initialize_with(&accounts, &mints, 3).is_ok());
+
+        for (accounts, mints) in [(&accounts[..2], &mints[..]), (&accounts[..], &mints[..2])] {
+            assert_eq!(
+                initialize_with(accounts, mints, 3),
+                Err(SwapError::InvalidNCoins.into())
+            );
+        }
+        assert_eq!(
+            initialize_with(&accounts, &mints, 2),
+            Err(SwapError::InvalidNCoins.into())
+        );
+    }
+
+    #[test]
+    fn test_initialize_duplicated_token_account() {
+        let mints: Vec<Pubkey> = (0..3).map(|_| Pubkey::new_unique()).collect();
+        let distinct: Vec<Pubkey> = (0..3).map(|_| Pubkey::new_unique()).collect();
+        assert!(initialize_with(&distinct, &mints, 3).is_ok());
+
+        let duplicated = [distinct[0], distinct[1], distinct[0]];
+        assert_eq!(
+            initialize_with(&duplicated, &mints, 3),
+            Err(SwapError::InvalidTokenAccount.into())
+        );
+    }
+
+    #[test]
+    fn test_exchange_in_amount() {
+        let state = three_coin_state();
+        assert_eq!(exchange_with(&state, 0), Err(SwapError::ZeroAmount.into()));
+        assert_eq!(
+            SwapInstruction::unpack(&exchange_with(&state, 10).unwrap().data),
+            Ok(SwapInstruction::Exchange {
+                in_amount: 10,
+                minimum_out_amount: 1,
+            })
+        );
+    }
+
+    #[test]
+    fn test_initialize_data_len() {
+        assert_eq!(initialize_instruction().pack().len(), INITIALIZE_DATA_LEN);
+        assert_eq!(initialize_instruction().expected_len(), INITIALIZE_DATA_LEN);
+    }
+
+    fn exchange_bps_with(max_slippage_bps: u16) -> Result<Instruction, ProgramError> {
+        let state = three_coin_state();
+        exchange_bps(
+            &crate::id(),
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+            state.token_account_addresses.iter().collect(),
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+            10,
+            max_slippage_bps,
+        )
+    }
+
+    #[test]
+    fn test_exchange_bps_round_trip() {
+        for max_slippage_bps in [0, 50, MAX_SLIPPAGE_BPS] {
+            let instruction = exchange_bps_with(max_slippage_bps).unwrap();
+            let mut expected = vec![30];
+            expected.extend_from_slice(&10u64.to_le_bytes());
+            expected.extend_from_slice(&max_slippage_bps.to_le_bytes());
+            assert_eq!(instruction.data, expected);
+            assert_eq!(
+                SwapInstruction::unpack(&instruction.data),
+                Ok(SwapInstruction::ExchangeBps {
+                    in_amount: 10,
+                    max_slippage_bps,
+                })
+            );
+        }
+    }
+
+    #[test]
+    fn test_exchange_bps_slippage_bound() {
+        assert_eq!(
+            exchange_bps_with(MAX_SLIPPAGE_BPS + 1),
+            Err(SwapError::InvalidSlippage.into())
+        );
+
+        let data = SwapInstruction::ExchangeBps {
+            in_amount: 10,
+            max_slippage_bps: MAX_SLIPPAGE_BPS + 1,
+        }
+        .pack();
+        assert_eq!(
+            SwapInstruction::unpack(&data),
+            Err(SwapError::InvalidSlippage.into())
+        );
+    }
+
+    #[test]
+    fn test_decode_virtual_price_return() {
+        let virtual_price: u128 = 1_000_123_456_789_000_000;
+        assert_eq!(
+            decode_virtual_price_return(&virtual_price.to_le_bytes()),
+            Ok(virtual_price)
+        );
+        assert_eq!(
+            decode_virtual_price_return(&virtual_price.to_le_bytes()[..15]),
+            Err(SwapError::InvalidConversion.into())
+        );
+        assert_eq!(
+            decode_virtual_price_return(&[]),
+            Err(SwapError::InvalidConversion.into())
+        );
+    }
+
+    #[test]
+    fn test_unpack_u16() {
+        let mut buf = vec![];
+        SwapInstruction::pack_u16(&mut buf, 10_000);
+        assert_eq!(buf, vec![0x10, 0x27]);
+
+        assert_eq!(SwapInstruction::unpack_u16(&buf), Ok((10_000, &[][..])));
+        assert_eq!(
+            SwapInstruction::unpack_u16(&[0x10, 0x27, 0xaa, 0xbb]),
+            Ok((10_000, &[0xaa, 0xbb][..]))
+        );
+        for short in [&[][..], &[0x10][..]] {
+            assert_eq!(
+                SwapInstruction::unpack_u16(short),
+                Err(SwapError::InvalidInstruction.into())
+            );
+        }
+    }
+
+    #[test]
+    fn test_migrate_round_trip() {
+        let instruction = SwapInstruction::Migrate {};
+        assert_eq!(instruction.pack(), vec![27]);
+        assert_eq!(SwapInstruction::unpack(&[27]), Ok(instruction));
+    }
+
+    #[test]
+    fn test_migrate_accounts() {
+        let swap_account = Pubkey::new_unique();
+        let payer = Pubkey::new_unique();
+        let migrate = migrate(
+            &crate::id(),
+            &swap_account,
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+            &payer,
+        )
+        .unwrap();
+
+        assert_eq!(migrate.data, vec![27]);
+        assert_eq!(migrate.accounts.len(), 6);
+        assert_eq!(migrate.accounts[0], AccountMeta::new(swap_account, false));
+        assert_eq!(migrate.accounts[3], AccountMeta::new(payer, true));
+        assert_eq!(
+            migrate.accounts[4],
+            AccountMeta::new_readonly(system_program::id(), false)
+        );
+        assert_eq!(
+            migrate.accounts[5],
+            AccountMeta::new_readonly(sysvar::rent::id(), false)
+        );
+    }
+
+    #[test]
+    fn test_migration_top_up() {
+        let rent = Rent::default();
+        let rent_exempt = rent.minimum_balance(SwapVersion::LATEST_LEN);
+        assert_eq!(migration_top_up(&rent, rent_exempt - 1_000), 1_000);
+        assert_eq!(migration_top_up(&rent, rent_exempt), 0);
+        assert_eq!(migration_top_up(&rent, rent_exempt + 1), 0);
+    }
+
+    fn collect_protocol_fees_with(
+        swap_token_accounts: &[Pubkey],
+        treasury_token_account: &Pubkey,
+    ) -> Result<Instruction, ProgramError> {
+        collect_protocol_fees(
+            &crate::id(),
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+            swap_token_accounts.iter().collect(),
+            treasury_token_account,
+        )
+    }
+
+    #[test]
+    fn test_collect_protocol_fees_round_trip() {
+        let instruction = SwapInstruction::CollectProtocolFees {};
+        assert_eq!(instruction.pack(), vec![29]);
+        assert_eq!(SwapInstruction::unpack(&[29]), Ok(instruction));
+    }
+
+    #[test]
+    fn test_collect_protocol_fees_accounts() {
+        let state = three_coin_state();
+        let treasury = Pubkey::new_unique();
+        let instruction =
+            collect_protocol_fees_with(&state.token_account_addresses, &treasury).unwrap();
+
+        let accounts = &instruction.accounts;
+        assert_eq!(accounts.len(), 5 + state.n_coins() + 1);
+        assert!(accounts[0].is_writable);
+        assert!(accounts[2].is_signer);
+        for (meta, token_account) in accounts[5..].iter().zip(&state.token_account_addresses) {
+            assert_eq!(*meta, AccountMeta::new(*token_account, false));
+        }
+        assert_eq!(*accounts.last().unwrap(), AccountMeta::new(treasury, false));
+    }
+
+    #[test]
+    fn test_collect_protocol_fees_treasury_is_pool_account() {
+        let state = three_coin_state();
+        assert_eq!(
+            collect_protocol_fees_with(
+                &state.token_account_addresses,
+                &state.token_account_addresses[1]
+            ),
+            Err(SwapError::InvalidTokenAccount.into())
+        );
+    }
+
+    fn add_liquidity_accounts(
+        token_program_address: &Pubkey,
+        n_sources: usize,
+    ) -> Result<Instruction, ProgramError> {
+        let swap_token_accounts = [Pubkey::new_unique(), Pubkey::new_unique()];
+        let source_token_accounts: Vec<Pubkey> =
+            (0..n_sources).map(|_| Pubkey::new_unique()).collect();
+        add_liquidity(
+            &crate::id(),
+            &Pubkey::new_unique(),
+            token_program_address,
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+            swap_token_accounts.iter().collect(),
+            &Pubkey::new_unique(),
+            source_token_accounts.iter().collect(),
+            &Pubkey::new_unique(),
+            vec![1, 2],
+            1,
+        )
+    }
+
+    #[test]
+    fn test_add_liquidity_rejections() {
+        let token_program = Pubkey::new_unique();
+        assert!(add_liquidity_accounts(&token_program, 2).is_ok());
+        for n_sources in [1, 3] {
+            assert_eq!(
+                add_liquidity_accounts(&token_program, n_sources),
+                Err(ProgramError::Custom(SwapError::InvalidNCoins as u32))
+            );
+        }
+        assert_eq!(
+            add_liquidity_accounts(&crate::id(), 2),
+            Err(ProgramError::Custom(
+                SwapError::IncorrectTokenProgramId as u32
+            ))
+        );
+    }
+
+    fn add_liquidity_batch_with(
+        token_program_address: &Pubkey,
+        user_sources: &[usize],
+        entries: Vec<(Vec<u64>, u64)>,
+    ) -> Result<Instruction, ProgramError> {
+        let swap_token_accounts = [Pubkey::new_unique(), Pubkey::new_unique()];
+        let user_authority = Pubkey::new_unique();
+        let lp_token_account = Pubkey::new_unique();
+        let source_token_accounts: Vec<Vec<Pubkey>> = user_sources
+            .iter()
+            .map(|&n_sources| (0..n_sources).map(|_| Pubkey::new_unique()).collect())
+            .collect();
+        let user_accounts = source_token_accounts
+            .iter()
+            .map(|sources| BatchDepositAccounts {
+                user_transfer_authority_address: &user_authority,
+                source_token_addresses: sources.iter().collect(),
+                lp_token_account_address: &lp_token_account,
+            })
+            .collect();
+        add_liquidity_batch(
+            &crate::id(),
+            &Pubkey::new_unique(),
+            token_program_address,
+            &Pubkey::new_unique(),
+            swap_token_accounts.iter().collect(),
+            &Pubkey::new_unique(),
+            user_accounts,
+            entries,
+        )
+    }
+
+    #[test]
+    fn test_add_liquidity_batch_rejections() {
+        let token_program = Pubkey::new_unique();
+        let entry = (vec![1, 2], 3);
+        assert!(add_liquidity_batch_with(&token_program, &[2, 2], vec![entry.clone(); 2]).is_ok());
+
+        assert_eq!(
+            add_liquidity_batch_with(&token_program, &[2], vec![entry.clone(); 2]),
+            Err(ProgramError::Custom(SwapError::ExpectedAccount as u32))
+        );
+        assert_eq!(
+            add_liquidity_batch_with(&token_program, &[2, 3], vec![entry.clone(); 2]),
+            Err(ProgramError::Custom(SwapError::InvalidNCoins as u32))
+        );
+        assert_eq!(
+            add_liquidity_batch_with(
+                &token_program,
+                &[2; MAX_BATCH + 1],
+                vec![entry.clone(); MAX_BATCH + 1]
+            ),
+            Err(ProgramError::Custom(SwapError::InvalidInstruction as u32))
+        );
+        assert_eq!(
+            add_liquidity_batch_with(&crate::id(), &[2], vec![entry]),
+            Err(ProgramError::Custom(
+                SwapError::IncorrectTokenProgramId as u32
+            ))
+        );
+    }
+
+    fn remove_liquidity_counts(
+        n_destinations: usize,
+        minimum_amounts: Vec<u64>,
+    ) -> Result<Instruction, ProgramError> {
+        let swap_token_accounts = [Pubkey::new_unique(), Pubkey::new_unique()];
+        let destination_token_accounts: Vec<Pubkey> =
+            (0..n_destinations).map(|_| Pubkey::new_unique()).collect();
+        remove_liquidity(
+            &crate::id(),
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+            swap_token_accounts.iter().collect(),
+            &Pubkey::new_unique(),
+            destination_token_accounts.iter().collect(),
+            &Pubkey::new_unique(),
+            10,
+            minimum_amounts,
+        )
+    }
+
+    #[test]
+    fn test_remove_liquidity_rejections() {
+        assert!(remove_liquidity_counts(2, vec![1, 2]).is_ok());
+        for (n_destinations, minimum_amounts) in [
+            (1, vec![1, 2]),
+            (3, vec![1, 2]),
+            (2, vec![1]),
+            (2, vec![1, 2, 3]),
+        ] {
+            assert_eq!(
+                remove_liquidity_counts(n_destinations, minimum_amounts),
+                Err(ProgramError::Custom(SwapError::InvalidNCoins as u32))
+            );
+        }
+        assert_eq!(
+            remove_liquidity_with(&crate::id(), 10),
+            Err(ProgramError::Custom(
+                SwapError::IncorrectTokenProgramId as u32
+            ))
+        );
+        assert_eq!(
+            remove_liquidity_with(&Pubkey::new_unique(), 0),
+            Err(ProgramError::Custom(SwapError::ZeroUnmint as u32))
+        );
+    }
+
+    #[test]
+    fn test_pool_mint_writability() {
+        let program_id = crate::id();
+        let swap_account = Pubkey::new_unique();
+        let token_program = Pubkey::new_unique();
+        let authority = Pubkey::new_unique();
+        let user_authority = Pubkey::new_unique();
+        let pool_mint = Pubkey::new_unique();
+        let lp_token_account = Pubkey::new_unique();
+        let swap_token_accounts = [Pubkey::new_unique(), Pubkey::new_unique()];
+        let user_token_accounts = [Pubkey::new_unique(), Pubkey::new_unique()];
+
+        let matrix = [
+            (
+                add_liquidity(
+                    &program_id,
+                    &swap_account,
+                    &token_program,
+                    &authority,
+                    &user_authority,
+                    swap_token_accounts.iter().collect(),
+                    &pool_mint,
+                    user_token_accounts.iter().collect(),
+                    &lp_token_account,
+                    vec![1, 2],
+                    1,
+                ),
+                true,
+            ),
+            (
+                remove_liquidity(
+                    &program_id,
+                    &swap_account,
+                    &token_program,
+                    &authority,
+                    &user_authority,
+                    swap_token_accounts.iter().collect(),
+                    &pool_mint,
+                    user_token_accounts.iter().collect(),
+                    &lp_token_account,
+                    10,
+                    vec![1, 2],
+                ),
+                true,
+            ),
+            (
+                remove_liquidity_one_token(
+                    &program_id,
+                    &swap_account,
+                    &token_program,
+                    &authority,
+                    &user_authority,
+                    swap_token_accounts.iter().collect(),
+                    &pool_mint,
+                    &user_token_accounts[0],
+                    &lp_token_account,
+                    10,
+                    1,
+                ),
+                true,
+            ),
+            (
+                get_virtual_price(
+                    &program_id,
+                    &swap_account,
+                    &token_program,
+                    swap_token_accounts.iter().collect(),
+                    &pool_mint,
+                ),
+                false,
+            ),
+        ];
+        for (instruction, writable) in matrix {
+            let instruction = instruction.unwrap();
+            assert_eq!(
+                validate_pool_mint_writability(&instruction, &pool_mint, writable),
+                Ok(())
+            );
+            assert_eq!(
+                validate_pool_mint_writability(&instruction, &pool_mint, !writable),
+                Err(SwapError::InvalidMint)
+            );
+            assert_eq!(
+                validate_pool_mint_writability(&instruction, &Pubkey::new_unique(), writable),
+                Err(SwapError::InvalidMint)
+            );
+        }
+    }
+
+    fn enabled_three_coin_state() -> SwapV2 {
+        SwapV2 {
+            admin_settings: AdminSettings {
+                swap_enabled: true,
+                add_liquidity_enabled: true,
+            },
+            ..three_coin_state()
+        }
+    }
+
+    #[test]
+    fn test_assert_instruction_matches_state_add_liquidity() {
+        let state = enabled_three_coin_state();
+        let add_liquidity = |deposit_amounts: Vec<u64>| SwapInstruction::AddLiquidity {
+            deposit_amounts,
+            min_mint_amount: 1,
+        };
+        assert_eq!(
+            assert_instruction_matches_state(&add_liquidity(vec![1, 2, 3]), &state),
+            Ok(())
+        );
+        assert_eq!(
+            assert_instruction_matches_state(&add_liquidity(vec![1, 2]), &state),
+            Err(SwapError::InvalidNCoins)
+        );
+    }
+
+    #[test]
+    fn test_assert_instruction_matches_state_exchange() {
+        let exchange = SwapInstruction::Exchange {
+            in_amount: 10,
+            minimum_out_amount: 9,
+        };
+        let mut state = enabled_three_coin_state();
+        assert_eq!(assert_instruction_matches_state(&exchange, &state), Ok(()));
+
+        state.admin_settings.swap_enabled = false;
+        assert_eq!(
+            assert_instruction_matches_state(&exchange, &state),
+            Err(SwapError::SwapDisabled)
+        );
+    }
+
+    #[test]
+    fn test_estimated_compute_units_grow_with_coins() {
+        let add_liquidity = |n_coins: usize| SwapInstruction::AddLiquidity {
+            deposit_amounts: vec![1; n_coins],
+            min_mint_amount: 1,
+        };
+        assert!(
+            add_liquidity(4).estimated_compute_units()
+                >= add_liquidity(2).estimated_compute_units()
+        );
+    }
+
+    #[test]
+    fn test_bytes_equal() {
+        let instruction = SwapInstruction::Exchange {
+            in_amount: 10,
+            minimum_out_amount: 9,
+        };
+        let mut packed = instruction.pack();
+        assert!(instruction.bytes_equal(&packed));
+
+        packed[1] ^= 1;
+        assert!(!instruction.bytes_equal(&packed));
+        assert!(!instruction.bytes_equal(&packed[..packed.len() - 1]));
+    }
+
+    #[test]
+    fn test_exchange_with_memo_zeroed_memo_round_trip() {
+        let instruction = SwapInstruction::ExchangeWithMemo {
+            in_amount: 10,
+            minimum_out_amount: 9,
+            memo: [0; 16],
+        };
+        let packed = instruction.pack();
+        let mut expected = vec![25];
+        expected.extend_from_slice(&10u64.to_le_bytes());
+        expected.extend_from_slice(&9u64.to_le_bytes());
+        expected.extend_from_slice(&[0; 16]);
+        assert_eq!(packed, expected);
+        assert_eq!(SwapInstruction::unpack(&packed), Ok(instruction));
+    }
+
+    #[test]
+    fn test_writable_accounts() {
+        let swap_token_accounts = [Pubkey::new_unique(), Pubkey::new_unique()];
+        let source_token_accounts = [Pubkey::new_unique(), Pubkey::new_unique()];
+        let pool_mint = Pubkey::new_unique();
+        let lp_token_account = Pubkey::new_unique();
+        let instruction = add_liquidity(
+            &crate::id(),
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+            swap_token_accounts.iter().collect(),
+            &pool_mint,
+            source_token_accounts.iter().collect(),
+            &lp_token_account,
+            vec![1, 2],
+            1,
+        )
+        .unwrap();
+
+        assert_eq!(
+            writable_accounts(&instruction),
+            vec![
+                swap_token_accounts[0],
+                swap_token_accounts[1],
+                pool_mint,
+                source_token_accounts[0],
+                source_token_accounts[1],
+                lp_token_account,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_order_swap_token_accounts_shuffled() {
+        let state = three_coin_state();
+        let [a, b, c] = [0, 1, 2].map(|i| &state.token_account_addresses[i]);
+        assert_eq!(
+            order_swap_token_accounts(&state, &[c, a, b]),
+            Ok(vec![a, b, c])
+        );
+        assert_eq!(
+            order_swap_token_accounts(&state, &[a, b, c]),
+            Ok(vec![a, b, c])
+        );
+    }
+
+    #[test]
+    fn test_order_swap_token_accounts_foreign_or_missing() {
+        let state = three_coin_state();
+        let [a, b, _] = [0, 1, 2].map(|i| &state.token_account_addresses[i]);
+        let foreign = Pubkey::new_unique();
+        assert_eq!(
+            order_swap_token_accounts(&state, &[a, b, &foreign]),
+            Err(SwapError::InvalidTokenAccount)
+        );
+        assert_eq!(
+            order_swap_token_accounts(&state, &[a, b]),
+            Err(SwapError::InvalidTokenAccount)
+        );
+    }
+
+    #[test]
+    fn test_exchange_from_state_matches_exchange() {
+        let state = three_coin_state();
+        let [swap_account, token_program, authority, user_authority, source, destination] =
+            [(); 6].map(|_| Pubkey::new_unique());
+
+        let from_state = exchange_from_state(
+            &crate::id(),
+            &swap_account,
+            &state,
+            &token_program,
+            &authority,
+            &user_authority,
+            &source,
+            &destination,
+            10,
+            9,
+        );
+        let manual = exchange(
+            &crate::id(),
+            &swap_account,
+            &token_program,
+            &authority,
+            &user_authority,
+            state.token_account_addresses.iter().collect(),
+            &source,
+            &destination,
+            10,
+            9,
+        );
+        assert!(manual.is_ok());
+        assert_eq!(from_state, manual);
+    }
+
+    #[test]
+    fn test_remove_liquidity_from_state_matches_remove_liquidity() {
+        let state = three_coin_state();
+        let [swap_account, token_program, authority, user_authority, lp_token_account] =
+            [(); 5].map(|_| Pubkey::new_unique());
+        let destinations = [(); 3].map(|_| Pubkey::new_unique());
+
+        let from_state = remove_liquidity_from_state(
+            &crate::id(),
+            &swap_account,
+            &state,
+            &token_program,
+            &authority,
+            &user_authority,
+            destinations.iter().collect(),
+            &lp_token_account,
+            10,
+            vec![1, 2, 3],
+        );
+        let manual = remove_liquidity(
+            &crate::id(),
+            &swap_account,
+            &token_program,
+            &authority,
+            &user_authority,
+            state.token_account_addresses.iter().collect(),
+            &state.pool_mint_address,
+            destinations.iter().collect(),
+            &lp_token_account,
+            10,
+            vec![1, 2, 3],
+        );
+        assert!(manual.is_ok());
+        assert_eq!(from_state, manual);
+    }
+
+    fn initialize_if_needed_with(
+        existing_state: Option<&[u8]>,
+    ) -> Result<Option<Instruction>, ProgramError> {
+        let token_accounts = [Pubkey::new_unique(), Pubkey::new_unique()];
+        let token_mints = [Pubkey::new_unique(), Pubkey::new_unique()];
+        initialize_if_needed(
+            existing_state,
+            &crate::id(),
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+            token_accounts.iter().collect(),
+            token_mints.iter().collect(),
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+            255,
+            2,
+            100,
+            4_000_000,
+            0,
+            AdminSettings::default(),
+        )
+    }
+
+    fn packed_state(is_initialized: bool) -> Vec<u8> {
+        let swap = SwapV2 {
+            is_initialized,
+            token_account_addresses: vec![Pubkey::new_unique(), Pubkey::new_unique()],
+            precision_multipliers: vec![1, 1],
+            ..SwapV2::default()
+        };
+        let mut packed = vec![0u8; 1 + SwapV2::LEN];
+        SwapVersion::pack(SwapVersion::SwapV2(swap), &mut packed).unwrap();
+        packed
+    }
+
+    #[test]
+    fn test_initialize_if_needed_initialized_state() {
+        assert_eq!(
+            initialize_if_needed_with(Some(&packed_state(true))),
+            Ok(None)
+        );
+    }
+
+    #[test]
+    fn test_initialize_if_needed_uninitialized_state() {
+        for state in [packed_state(false), vec![0u8; SwapVersion::LATEST_LEN]] {
+            let instruction = initialize_if_needed_with(Some(&state)).unwrap().unwrap();
+            assert_eq!(
+                SwapInstruction::peek_tag(&instruction.data),
+                Ok(SwapInstructionTag::Initialize)
+            );
+        }
+    }
+
+    #[test]
+    fn test_initialize_if_needed_empty_state() {
+        for state in [None, Some(&[][..])] {
+            let instruction = initialize_if_needed_with(state).unwrap().unwrap();
+            assert_eq!(
+                SwapInstruction::peek_tag(&instruction.data),
+                Ok(SwapInstructionTag::Initialize)
+            );
+        }
+    }
+
+    #[cfg(feature = "base64")]
+    #[test]
+    fn test_base64_round_trip() {
+        for instruction in [
+            SwapInstruction::Exchange {
+                in_amount: 10,
+                minimum_out_amount: 9,
+            },
+            SwapInstruction::AddLiquidity {
+                deposit_amounts: vec![1, 2, 3],
+                min_mint_amount: 4,
+            },
+            SwapInstruction::GetVirtualPrice {},
+        ] {
+            let encoded = instruction.pack_base64();
+            assert_eq!(SwapInstruction::unpack_base64(&encoded), Ok(instruction));
+        }
+        assert_eq!(SwapInstruction::GetVirtualPrice {}.pack_base64(), "BQ==");
+    }
+
+    #[cfg(feature = "base64")]
+    #[test]
+    fn test_unpack_base64_invalid_encoding() {
+        assert_eq!(
+            SwapInstruction::unpack_base64("not base64!"),
+            Err(SwapError::InvalidInstruction.into())
+        );
+    }
+
+    #[test]
+    fn test_admin_account_metas_ordering() {
+        let [swap_account, admin_token_account, nft_owner] = [(); 3].map(|_| Pubkey::new_unique());
+        let expected = vec![
+            AccountMeta::new(swap_account, false),
+            AccountMeta::new_readonly(admin_token_account, false),
+            AccountMeta::new_readonly(nft_owner, true),
+        ];
+        assert_eq!(
+            admin_account_metas(&swap_account, &admin_token_account, &nft_owner),
+            expected
+        );
+
+        let program_id = crate::id();
+        let admin_instructions = [
+            set_admin_setting(
+                &program_id,
+                &swap_account,
+                &admin_token_account,
+                &nft_owner,
+                AdminSetting::SetSwapEnabled(true),
+            ),
+            set_admin_settings(
+                &program_id,
+                &swap_account,
+                &admin_token_account,
+                &nft_owner,
+                AdminSettings::default(),
+            ),
+            emergency_freeze(&program_id, &swap_account, &admin_token_account, &nft_owner),
+            collect_protocol_fees(
+                &program_id,
+                &swap_account,
+                &admin_token_account,
+                &nft_owner,
+                &Pubkey::new_unique(),
+                &Pubkey::new_unique(),
+                vec![&Pubkey::new_unique()],
+                &Pubkey::new_unique(),
+            ),
+        ];
+        for instruction in admin_instructions {
+            assert_eq!(instruction.unwrap().accounts[..3], expected[..]);
+        }
+        let migrate = migrate(
+            &program_id,
+            &swap_account,
+            &admin_token_account,
+            &nft_owner,
+            &Pubkey::new_unique(),
+        )
+        .unwrap();
+        assert_eq!(migrate.accounts[..3], expected[..]);
+    }
+
+    #[test]
+    fn test_exchange_same_source_and_destination() {
+        let state = three_coin_state();
+        let token_account = Pubkey::new_unique();
+        let exchange_between = |destination: &Pubkey| {
+            exchange(
+                &crate::id(),
+                &Pubkey::new_unique(),
+                &Pubkey::new_unique(),
+                &Pubkey::new_unique(),
+                &Pubkey::new_unique(),
+                state.token_account_addresses.iter().collect(),
+                &token_account,
+                destination,
+                10,
+                1,
+            )
+        };
+        assert_eq!(
+            exchange_between(&token_account),
+            Err(SwapError::InvalidExchangeAccount.into())
+        );
+        assert!(exchange_between(&Pubkey::new_unique()).is_ok());
+    }
+
+    #[test]
+    fn test_versioned_pack_round_trip() {
+        let instruction = SwapInstruction::Exchange {
+            in_amount: 10,
+            minimum_out_amount: 9,
+        };
+        let packed = instruction.pack_v(0).unwrap();
+        assert_eq!(packed[0], 0);
+        assert_eq!(packed[1..], instruction.pack()[..]);
+        assert_eq!(SwapInstruction::unpack_v(&packed), Ok(instruction.clone()));
+
+        assert_eq!(
+            instruction.pack_v(1),
+            Err(SwapError::InvalidInstruction.into())
+        );
+        let mut unknown_version = packed;
+        unknown_version[0] = 1;
+        assert_eq!(
+            SwapInstruction::unpack_v(&unknown_version),
+            Err(SwapError::InvalidInstruction.into())
+        );
+    }
+
+    #[test]
+    fn test_required_signers() {
+        let state = three_coin_state();
+        let exchange_ix = exchange_with(&state, 10).unwrap();
+        assert_eq!(
+            required_signers(&exchange_ix),
+            vec![exchange_ix.accounts[3].pubkey]
+        );
+
+        let accounts: Vec<Pubkey> = (0..3).map(|_| Pubkey::new_unique()).collect();
+        let mints: Vec<Pubkey> = (0..3).map(|_| Pubkey::new_unique()).collect();
+        let initialize_ix = initialize_with(&accounts, &mints, 3).unwrap();
+        assert!(required_signers(&initialize_ix).is_empty());
+    }
+
+    #[test]
+    fn test_from_instruction() {
+        let state = three_coin_state();
+        let mut ix = exchange_with(&state, 10).unwrap();
+        assert_eq!(
+            SwapInstruction::from_instruction(&ix),
+            Ok(SwapInstruction::Exchange {
+                in_amount: 10,
+                minimum_out_amount: 1,
+            })
+        );
+
+        ix.program_id = Pubkey::new_unique();
+        assert_eq!(
+            SwapInstruction::from_instruction(&ix),
+            Err(ProgramError::IncorrectProgramId)
+        );
+    }
+
+    #[cfg(feature = "anchor-meta")]
+    #[test]
+    fn test_exchange_account_context() {
+        let context = SwapInstructionTag::Exchange.account_context();
+        assert_eq!(
+            context,
+            vec![
+                ("swap", false, false),
+                ("token_program", false, false),
+                ("authority", false, false),
+                ("user_transfer_authority", false, true),
+                ("swap_token_accounts", true, false),
+                ("source_token_account", true, false),
+                ("destination_token_account", true, false),
+            ]
+        );
+
+        // Flags agree with the builder, the swap token accounts entry standing for every coin
+        let state = three_coin_state();
+        let metas = exchange_with(&state, 10).unwrap().accounts;
+        let n_coins = state.n_coins();
+        let expanded: Vec<_> = context[..4]
+            .iter()
+            .chain((0..n_coins).map(|_| &context[4]))
+            .chain(&context[5..])
+            .collect();
+        assert_eq!(expanded.len(), metas.len());
+        for ((_, is_writable, is_signer), meta) in expanded.into_iter().zip(&metas) {
+            assert_eq!(
+                (*is_writable, *is_signer),
+                (meta.is_writable, meta.is_signer)
+            );
+        }
+    }
+
+    #[test]
+    fn test_validate_one_token_destination_mint() {
+        let mints: Vec<Pubkey> = (0..3).map(|_| Pubkey::new_unique()).collect();
+        assert_eq!(
+            validate_one_token_destination_mint(&mints, &mints[1]),
+            Ok(())
+        );
+        assert_eq!(
+            validate_one_token_destination_mint(&mints, &Pubkey::new_unique()),
+            Err(SwapError::InvalidTokenMatch)
+        );
+    }
+
+    #[test]
+    fn test_validate_minimums() {
+        let pool_balances = [1_000, 2_000, 3_000];
+        assert_eq!(
+            validate_minimums(&[500, 1_000, 3_000], &pool_balances),
+            Ok(())
+        );
+        assert_eq!(
+            validate_minimums(&[500, u64::MAX, 1_500], &pool_balances),
+            Err(SwapError::ExceededSlippage)
+        );
+        assert_eq!(
+            validate_minimums(&[500, 1_000], &pool_balances),
+            Err(SwapError::InvalidNCoins)
+        );
+    }
+
+    #[cfg(feature = "debug-tools")]
+    #[test]
+    fn test_annotate_exchange() {
+        let packed = SwapInstruction::Exchange {
+            in_amount: 1000,
+            minimum_out_amount: 995,
+        }
+        .pack();
+        assert_eq!(
+            SwapInstruction::annotate(&packed).unwrap(),
+            "tag=4 Exchange | in_amount=1000 (bytes 1..9) | minimum_out_amount=995 (bytes 9..17)"
+        );
+        assert_eq!(
+            SwapInstruction::annotate(&packed[..9]),
+            Err(SwapError::InvalidInstruction.into())
+        );
+    }
+}
@@ -1,5 +1,8 @@
+#[cfg(feature = "client-checks")]
+pub mod client_checks;
 pub mod error;
 pub mod instruction;
+pub mod math;
 pub mod state;
 pub mod utils;
 
@@ -7,6 +10,9 @@ use solana_program::{entrypoint::ProgramResult, program_error::ProgramError, pub
 
 solana_program::declare_id!("MERLuDFBMmsHnsBPZw2sDQZHvXFMwp8EdjudcU2HKky");
 
+/// Program id as a string, for configuration. `declare_id!` stays the source of truth
+pub const PROGRAM_ID_STR: &str = "MERLuDFBMmsHnsBPZw2sDQZHvXFMwp8EdjudcU2HKky";
+
 /// Checks that the supplied program ID is the correct one for this program
 pub fn check_program_account(program_id: &Pubkey) -> ProgramResult {
     if program_id != &id() {
@@ -18,6 +24,21 @@ pub fn check_program_account(program_id: &Pubkey) -> ProgramResult {
 pub struct PoolParameter {}
 
 impl PoolParameter {
+    /// Minimum number of coins in a pool
+    pub const MIN_N_COINS: usize = 2;
     /// Maximum number of coins in a pool
     pub const MAX_N_COINS: usize = 4;
+    /// Denominator of the fee and admin fee numerators
+    pub const FEE_DENOMINATOR: u64 = 10_000_000_000;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    #[test]
+    fn test_program_id_str_matches_declared_id() {
+        assert_eq!(Pubkey::from_str(PROGRAM_ID_STR), Ok(id()));
+    }
 }
@@ -0,0 +1,392 @@
+//! Off-chain helpers for the pool arithmetic
+
+use solana_program::program_error::ProgramError;
+
+use crate::error::SwapError;
+use crate::instruction::MAX_SLIPPAGE_BPS;
+use crate::utils;
+use crate::PoolParameter;
+
+/// Splits a swap fee into `(lp_fee, admin_fee)`, the admin fee being
+/// `total_fee * admin_fee_numerator / FEE_DENOMINATOR`
+pub fn split_fee(total_fee: u128, admin_fee_numerator: u64) -> Result<(u128, u128), ProgramError> {
+    if admin_fee_numerator > PoolParameter::FEE_DENOMINATOR {
+        return Err(SwapError::InvalidFee.into());
+    }
+
+    let admin_fee = total_fee
+        .checked_mul(admin_fee_numerator as u128)
+        .ok_or(SwapError::InvalidCalculation)?
+        / PoolParameter::FEE_DENOMINATOR as u128;
+    let lp_fee = total_fee - admin_fee;
+
+    Ok((lp_fee, admin_fee))
+}
+
+/// Advisory admin fee numerator for a pool charging `fee_numerator`, sending half of the swap
+/// fees to the admin. Pools without swap fees have nothing to share.
+pub fn recommended_admin_fee(fee_numerator: u64) -> u64 {
+    if fee_numerator == 0 {
+        return 0;
+    }
+    PoolParameter::FEE_DENOMINATOR / 2
+}
+
+/// Value of the pool balances, each balance being worth `balance / 10^decimals * price_usd`.
+/// Prices share a fixed point scale of choice, which the result is expressed in.
+pub fn pool_tvl(
+    balances: &[u64],
+    decimals: &[u8],
+    prices_usd: &[u64],
+) -> Result<u128, ProgramError> {
+    if balances.len() != decimals.len() || balances.len() != prices_usd.len() {
+        return Err(SwapError::InvalidCalculation.into());
+    }
+
+    let mut tvl: u128 = 0;
+    for ((&balance, &decimals), &price_usd) in balances.iter().zip(decimals).zip(prices_usd) {
+        let value = (balance as u128)
+            .checked_mul(price_usd as u128)
+            .and_then(|value| value.checked_div(10u128.checked_pow(decimals as u32)?))
+            .ok_or(SwapError::InvalidCalculation)?;
+        tvl = tvl
+            .checked_add(value)
+            .ok_or(SwapError::InvalidCalculation)?;
+    }
+    Ok(tvl)
+}
+
+/// Share of the pool owned by `lp_amount` pool tokens, in basis points
+pub fn lp_share_bps(lp_amount: u64, total_supply: u64) -> Result<u16, ProgramError> {
+    if total_supply == 0 || lp_amount > total_supply {
+        return Err(SwapError::InvalidCalculation.into());
+    }
+    Ok((lp_amount as u128 * 10_000 / total_supply as u128) as u16)
+}
+
+/// Underlying amounts of each coin `lp_amount` pool tokens are a proportional claim on
+pub fn underlying_from_lp(
+    lp_amount: u64,
+    total_supply: u64,
+    balances: &[u64],
+) -> Result<Vec<u64>, ProgramError> {
+    if total_supply == 0 || lp_amount > total_supply {
+        return Err(SwapError::InvalidCalculation.into());
+    }
+    Ok(balances
+        .iter()
+        .map(|&balance| (balance as u128 * lp_amount as u128 / total_supply as u128) as u64)
+        .collect())
+}
+
+/// Expected outputs of a [crate::instruction::SwapInstruction::RemoveLiquidity] of
+/// `unmint_amount` pool tokens, `balance * unmint_amount / total_supply` for each coin
+pub fn calc_remove_liquidity(
+    balances: &[u64],
+    total_supply: u64,
+    unmint_amount: u64,
+) -> Result<Vec<u64>, ProgramError> {
+    underlying_from_lp(unmint_amount, total_supply, balances)
+}
+
+/// Deposits moving the balances towards `target_ratio` while spending at most `budget`, in the
+/// units of `current`. Each coin is filled up to its share of the balances after deposit;
+/// when those deficits exceed the budget they are scaled down proportionally.
+pub fn deposit_for_target_ratio(
+    current: &[u128],
+    target_ratio: &[u64],
+    budget: u128,
+) -> Result<Vec<u64>, ProgramError> {
+    if current.len() != target_ratio.len() {
+        return Err(SwapError::InvalidCalculation.into());
+    }
+    let ratio_sum: u128 = target_ratio.iter().map(|&ratio| ratio as u128).sum();
+    if ratio_sum == 0 {
+        return Err(SwapError::InvalidCalculation.into());
+    }
+
+    let total = current
+        .iter()
+        .try_fold(budget, |total, &balance| total.checked_add(balance))
+        .ok_or(SwapError::InvalidCalculation)?;
+
+    let mut deficits = Vec::with_capacity(current.len());
+    for (&balance, &ratio) in current.iter().zip(target_ratio) {
+        let target = total
+            .checked_mul(ratio as u128)
+            .ok_or(SwapError::InvalidCalculation)?
+            / ratio_sum;
+        deficits.push(target.saturating_sub(balance));
+    }
+
+    let deficit_sum = deficits
+        .iter()
+        .try_fold(0u128, |sum, &deficit| sum.checked_add(deficit))
+        .ok_or(SwapError::InvalidCalculation)?;
+    deficits
+        .into_iter()
+        .map(|deficit| {
+            let deposit = if deficit_sum > budget {
+                deficit
+                    .checked_mul(budget)
+                    .ok_or(SwapError::InvalidCalculation)?
+                    / deficit_sum
+            } else {
+                deficit
+            };
+            utils::u128_to_u64_amount(deposit)
+        })
+        .collect()
+}
+
+/// Scales token balances by their precision multipliers into the common precision the curve
+/// works in. The products are kept in u128, as multipliers up to 1e12 overflow u64.
+pub fn scale_balances(
+    balances: &[u64],
+    precision_multipliers: &[u64],
+) -> Result<Vec<u128>, ProgramError> {
+    if balances.len() != precision_multipliers.len() {
+        return Err(SwapError::InvalidCalculation.into());
+    }
+    Ok(balances
+        .iter()
+        .zip(precision_multipliers.iter())
+        .map(|(&balance, &multiplier)| balance as u128 * multiplier as u128)
+        .collect())
+}
+
+/// Converts a scaled amount back into token units, flooring away the part below the multiplier
+pub fn descale_amount(scaled: u128, multiplier: u64) -> Result<u64, ProgramError> {
+    if multiplier == 0 {
+        return Err(SwapError::InvalidPrecision.into());
+    }
+    utils::u128_to_u64_amount(scaled / multiplier as u128)
+}
+
+/// Minimum out amount of a [crate::instruction::SwapInstruction::Exchange] accepting a
+/// shortfall of `max_slippage_bps` from a u128 curve quote, failing if it does not fit the u64
+/// instruction field
+pub fn minimum_out_amount(
+    quoted_out_amount: u128,
+    max_slippage_bps: u16,
+) -> Result<u64, ProgramError> {
+    if max_slippage_bps > MAX_SLIPPAGE_BPS {
+        return Err(SwapError::InvalidSlippage.into());
+    }
+    let minimum_out_amount = quoted_out_amount
+        .checked_mul((MAX_SLIPPAGE_BPS - max_slippage_bps) as u128)
+        .ok_or(SwapError::InvalidCalculation)?
+        / MAX_SLIPPAGE_BPS as u128;
+    utils::u128_to_u64_amount(minimum_out_amount)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_fee() {
+        let denominator = PoolParameter::FEE_DENOMINATOR;
+        assert_eq!(split_fee(1_000, 0), Ok((1_000, 0)));
+        assert_eq!(split_fee(1_000, denominator / 2), Ok((500, 500)));
+        assert_eq!(split_fee(1_000, denominator), Ok((0, 1_000)));
+        // the admin share rounds down, leaving the remainder to the LPs
+        assert_eq!(split_fee(1_001, denominator / 2), Ok((501, 500)));
+    }
+
+    #[test]
+    fn test_split_fee_out_of_range() {
+        assert_eq!(
+            split_fee(1_000, PoolParameter::FEE_DENOMINATOR + 1),
+            Err(SwapError::InvalidFee.into())
+        );
+    }
+
+    #[test]
+    fn test_minimum_out_amount() {
+        assert_eq!(minimum_out_amount(1_000, 0), Ok(1_000));
+        assert_eq!(minimum_out_amount(1_000, 50), Ok(995));
+        assert_eq!(minimum_out_amount(1_000, MAX_SLIPPAGE_BPS), Ok(0));
+        assert_eq!(
+            minimum_out_amount(1_000, MAX_SLIPPAGE_BPS + 1),
+            Err(SwapError::InvalidSlippage.into())
+        );
+    }
+
+    #[test]
+    fn test_minimum_out_amount_overflowing_quote() {
+        assert_eq!(minimum_out_amount(u64::MAX as u128, 0), Ok(u64::MAX));
+        assert_eq!(
+            minimum_out_amount(u64::MAX as u128 + 1, 0),
+            Err(SwapError::InvalidConversion.into())
+        );
+    }
+
+    #[test]
+    fn test_pool_tvl_dollar_pool() {
+        // 1M USDC and 1M USDT of 6 decimals, both at $1 with 6 price decimals
+        let balances = [1_000_000_000_000, 1_000_000_000_000];
+        assert_eq!(
+            pool_tvl(&balances, &[6, 6], &[1_000_000, 1_000_000]),
+            Ok(2_000_000_000_000)
+        );
+        // a 9 decimals coin is worth the same as its 6 decimals counterpart
+        assert_eq!(
+            pool_tvl(
+                &[1_000_000_000_000, 1_000_000_000_000_000],
+                &[6, 9],
+                &[1_000_000, 1_000_000]
+            ),
+            Ok(2_000_000_000_000)
+        );
+    }
+
+    #[test]
+    fn test_pool_tvl_length_mismatch() {
+        assert_eq!(
+            pool_tvl(&[1, 2], &[6], &[1, 1]),
+            Err(SwapError::InvalidCalculation.into())
+        );
+        assert_eq!(
+            pool_tvl(&[1, 2], &[6, 6], &[1]),
+            Err(SwapError::InvalidCalculation.into())
+        );
+    }
+
+    #[test]
+    fn test_lp_share_bps() {
+        assert_eq!(lp_share_bps(0, 1_000), Ok(0));
+        assert_eq!(lp_share_bps(500, 1_000), Ok(5_000));
+        assert_eq!(lp_share_bps(1_000, 1_000), Ok(10_000));
+    }
+
+    #[test]
+    fn test_lp_share_bps_invalid_supply() {
+        assert_eq!(
+            lp_share_bps(0, 0),
+            Err(SwapError::InvalidCalculation.into())
+        );
+        assert_eq!(
+            lp_share_bps(1_001, 1_000),
+            Err(SwapError::InvalidCalculation.into())
+        );
+    }
+
+    #[test]
+    fn test_deposit_for_target_ratio_two_coins() {
+        // the budget fills the 100 coin up to the 300 one
+        assert_eq!(
+            deposit_for_target_ratio(&[100, 300], &[1, 1], 200),
+            Ok(vec![200, 0])
+        );
+        // a larger budget is split to keep the ratio
+        assert_eq!(
+            deposit_for_target_ratio(&[100, 300], &[1, 1], 400),
+            Ok(vec![300, 100])
+        );
+    }
+
+    #[test]
+    fn test_deposit_for_target_ratio_scaled_down() {
+        // deficits of 150 and 150 exceed the budget of 150, each gets half
+        assert_eq!(
+            deposit_for_target_ratio(&[100, 100, 400], &[1, 1, 1], 150),
+            Ok(vec![75, 75, 0])
+        );
+    }
+
+    #[test]
+    fn test_deposit_for_target_ratio_invalid_input() {
+        assert_eq!(
+            deposit_for_target_ratio(&[100, 300], &[1], 200),
+            Err(SwapError::InvalidCalculation.into())
+        );
+        assert_eq!(
+            deposit_for_target_ratio(&[100, 300], &[0, 0], 200),
+            Err(SwapError::InvalidCalculation.into())
+        );
+    }
+
+    #[test]
+    fn test_calc_remove_liquidity_half() {
+        assert_eq!(
+            calc_remove_liquidity(&[1_000, 2_001], 100, 50),
+            Ok(vec![500, 1_000])
+        );
+    }
+
+    #[test]
+    fn test_calc_remove_liquidity_full() {
+        assert_eq!(
+            calc_remove_liquidity(&[1_000, 2_001], 100, 100),
+            Ok(vec![1_000, 2_001])
+        );
+        assert_eq!(
+            underlying_from_lp(100, 100, &[1_000, 2_001]),
+            Ok(vec![1_000, 2_001])
+        );
+    }
+
+    #[test]
+    fn test_calc_remove_liquidity_invalid_supply() {
+        assert_eq!(
+            calc_remove_liquidity(&[1_000], 0, 0),
+            Err(SwapError::InvalidCalculation.into())
+        );
+        assert_eq!(
+            calc_remove_liquidity(&[1_000], 100, 101),
+            Err(SwapError::InvalidCalculation.into())
+        );
+    }
+
+    #[test]
+    fn test_scale_descale_nine_and_six_decimals() {
+        // a 9 decimals coin and a 6 decimals coin, scaled to 9 decimals
+        let multipliers = [1, 1_000];
+        let scaled = scale_balances(&[1_234_567_891, 1_234_567], &multipliers).unwrap();
+        assert_eq!(scaled, vec![1_234_567_891, 1_234_567_000]);
+
+        // one 6 decimals token is one 9 decimals token once scaled
+        assert_eq!(
+            scale_balances(&[1_000_000_000, 1_000_000], &multipliers),
+            Ok(vec![1_000_000_000, 1_000_000_000])
+        );
+
+        // scaling then descaling gives back every amount, up to u64::MAX on the u128 path
+        for amount in [0, 1, 999, 1_234_567, u64::MAX - 1, u64::MAX] {
+            let scaled = scale_balances(&[amount, amount], &multipliers).unwrap();
+            assert_eq!(scaled[1], amount as u128 * 1_000);
+            assert_eq!(descale_amount(scaled[0], 1), Ok(amount));
+            assert_eq!(descale_amount(scaled[1], 1_000), Ok(amount));
+        }
+        // moving the 9 decimals amount to the 6 decimals coin loses the sub-multiplier remainder
+        let descaled = descale_amount(scaled[0], 1_000).unwrap();
+        assert_eq!(descaled, 1_234_567);
+        assert!(scaled[0] - descaled as u128 * 1_000 < 1_000);
+    }
+
+    #[test]
+    fn test_scale_balances_does_not_narrow() {
+        let multiplier = 1_000_000_000_000;
+        let scaled = scale_balances(&[u64::MAX], &[multiplier]).unwrap();
+        assert_eq!(scaled, vec![u64::MAX as u128 * multiplier as u128]);
+        assert_eq!(descale_amount(scaled[0], multiplier), Ok(u64::MAX));
+    }
+
+    #[test]
+    fn test_descale_amount_zero_multiplier() {
+        assert_eq!(
+            descale_amount(1_000, 0),
+            Err(SwapError::InvalidPrecision.into())
+        );
+    }
+
+    #[test]
+    fn test_recommended_admin_fee() {
+        assert_eq!(recommended_admin_fee(0), 0);
+        assert_eq!(
+            recommended_admin_fee(4_000_000),
+            PoolParameter::FEE_DENOMINATOR / 2
+        );
+    }
+}
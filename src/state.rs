@@ -1,3 +1,5 @@
+use crate::error::SwapError;
+use crate::utils;
 use crate::PoolParameter;
 use arrayref::{array_mut_ref, array_ref, array_refs, mut_array_refs};
 use solana_program::{
@@ -5,6 +7,7 @@ use solana_program::{
     program_pack::{IsInitialized, Pack, Sealed},
     pubkey::Pubkey,
 };
+use std::convert::TryFrom;
 
 #[repr(C)]
 #[derive(Copy, Clone, Debug, Default, PartialEq)]
@@ -13,13 +16,69 @@ pub struct AdminSettings {
     pub add_liquidity_enabled: bool,
 }
 
+impl TryFrom<&[u8]> for AdminSettings {
+    type Error = ProgramError;
+
+    /// Decodes the two admin settings flags, each being 0 or 1
+    fn try_from(input: &[u8]) -> Result<Self, Self::Error> {
+        match input {
+            [swap_enabled, add_liquidity_enabled] => Ok(AdminSettings {
+                swap_enabled: utils::u8_to_bool(*swap_enabled)?,
+                add_liquidity_enabled: utils::u8_to_bool(*add_liquidity_enabled)?,
+            }),
+            _ => Err(ProgramError::InvalidAccountData),
+        }
+    }
+}
+
+impl AdminSettings {
+    /// Encodes the two admin settings flags, the inverse of [AdminSettings::try_from]
+    pub fn to_bytes(&self) -> [u8; 2] {
+        [
+            utils::bool_to_u8(self.swap_enabled),
+            utils::bool_to_u8(self.add_liquidity_enabled),
+        ]
+    }
+}
+
+/// Version byte prefixing a packed [SwapVersion]
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StateVersion {
+    V2 = 2,
+    V3 = 3,
+}
+
+impl StateVersion {
+    /// Version written by [SwapVersion::pack] for the latest state
+    pub const LATEST: Self = Self::V3;
+}
+
+impl TryFrom<u8> for StateVersion {
+    type Error = ProgramError;
+
+    /// Parses a version byte, a zero byte being the version of an account that was never
+    /// initialized
+    fn try_from(version: u8) -> Result<Self, Self::Error> {
+        match version {
+            2 => Ok(Self::V2),
+            3 => Ok(Self::V3),
+            0 => Err(ProgramError::UninitializedAccount),
+            _ => Err(SwapError::UnsupportedVersion.into()),
+        }
+    }
+}
+
 #[derive(Clone, Debug, PartialEq)]
 pub enum SwapVersion {
-    /// Latest version
+    /// Previous version, only usable for migration
     SwapV2(SwapV2),
+    /// Latest version
+    SwapV3(SwapV3),
 }
 
-/// Current used state, previous state is only usable for migration
+/// Current used state, previous state is only usable for migration.
+/// Stays [SwapV2] for this release, [SwapV3] only being the migration target
 pub type SwapState = SwapV2;
 
 #[repr(C)]
@@ -47,42 +106,227 @@ pub struct SwapV2 {
     pub admin_settings: AdminSettings,
 }
 
+/// Latest version of the swap state, the [SwapV2] fields followed by the cached authority
+#[repr(C)]
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SwapV3 {
+    /// Fields shared with the previous version
+    pub swap: SwapV2,
+    /// Program address derived from the nonce, cached so read paths skip
+    /// `create_program_address`
+    pub authority: Pubkey,
+}
+
 impl SwapVersion {
     /// Size of the latest version of the SwapState
-    pub const LATEST_LEN: usize = 1 + SwapV2::LEN; // add one for the version enum
+    pub const LATEST_LEN: usize = 1 + SwapV3::LEN; // add one for the version enum
 
     /// Pack a swap into a byte array, based on its version
     pub fn pack(src: Self, dst: &mut [u8]) -> Result<(), ProgramError> {
         match src {
             Self::SwapV2(swap_info) => {
-                dst[0] = 2;
+                dst[0] = StateVersion::V2 as u8;
                 SwapV2::pack(swap_info, &mut dst[1..])
             }
+            Self::SwapV3(swap_info) => {
+                dst[0] = StateVersion::V3 as u8;
+                SwapV3::pack(swap_info, &mut dst[1..])
+            }
         }
     }
 
+    /// Whether the version byte is one [SwapVersion::unpack] can read
+    pub fn supported_version(version: u8) -> bool {
+        StateVersion::try_from(version).is_ok()
+    }
+
+    /// Splits packed swap data into its version byte and the versioned state
+    pub fn split_version(input: &[u8]) -> Result<(u8, &[u8]), ProgramError> {
+        input
+            .split_first()
+            .map(|(&version, rest)| (version, rest))
+            .ok_or(ProgramError::InvalidAccountData)
+    }
+
     /// Unpack the swap account based on its version, returning the result as a
     /// SwapState trait object
     pub fn unpack(input: &[u8]) -> Result<SwapVersion, ProgramError> {
-        let (&version, rest) = input
-            .split_first()
-            .ok_or(ProgramError::InvalidAccountData)?;
-        match version {
-            2 => Ok(Self::SwapV2(SwapV2::unpack(rest)?)),
-            _ => Err(ProgramError::UninitializedAccount),
+        let (version, rest) = Self::split_version(input)?;
+        match StateVersion::try_from(version)? {
+            StateVersion::V2 => Ok(Self::SwapV2(SwapV2::unpack(rest)?)),
+            StateVersion::V3 => Ok(Self::SwapV3(SwapV3::unpack(rest)?)),
         }
     }
 
+    /// Reads the nonce of a packed swap without unpacking the rest of the state. Version 3 is
+    /// accepted along with version 2 as it starts with the version 2 layout, so the nonce is
+    /// at the same offset in both.
+    pub fn read_nonce(input: &[u8]) -> Result<u8, ProgramError> {
+        let (version, rest) = Self::split_version(input)?;
+        StateVersion::try_from(version)?;
+        rest.get(1).copied().ok_or(ProgramError::InvalidAccountData)
+    }
+
     /// Special check to be done before any instruction processing, works for
-    /// all versions
+    /// all versions. Empty or unknown input is reported as uninitialized
     pub fn is_initialized(input: &[u8]) -> bool {
-        match Self::unpack(input) {
-            Ok(swap) => match swap {
-                Self::SwapV2(swapv2) => swapv2.is_initialized,
-            },
-            Err(_) => false,
+        matches!(Self::unpack(input), Ok(swap) if swap.initialized_flag())
+    }
+
+    /// The is_initialized flag of the unpacked swap, whatever its version
+    fn initialized_flag(&self) -> bool {
+        match self {
+            Self::SwapV2(swapv2) => swapv2.is_initialized,
+            Self::SwapV3(swapv3) => swapv3.swap.is_initialized,
+        }
+    }
+}
+
+impl SwapV2 {
+    /// Creates an initialized [SwapV2], checking that the pool holds
+    /// [PoolParameter::MIN_N_COINS] to [PoolParameter::MAX_N_COINS] coins each with a precision
+    /// multiplier, that token accounts are distinct, that the amplification coefficient is
+    /// non-zero and that fees are within [PoolParameter::FEE_DENOMINATOR]
+    #[allow(clippy::too_many_arguments)]
+    pub fn try_new(
+        nonce: u8,
+        amplification_coefficient: u64,
+        fee_numerator: u64,
+        admin_fee_numerator: u64,
+        precision_factor: u64,
+        precision_multipliers: Vec<u64>,
+        token_account_addresses: Vec<Pubkey>,
+        pool_mint_address: Pubkey,
+        admin_token_mint_address: Pubkey,
+        admin_settings: AdminSettings,
+    ) -> Result<Self, SwapError> {
+        if precision_multipliers.len() != token_account_addresses.len()
+            || token_account_addresses.len() < PoolParameter::MIN_N_COINS
+            || token_account_addresses.len() > PoolParameter::MAX_N_COINS
+        {
+            return Err(SwapError::InvalidNCoins);
+        }
+        for (i, token_account_address) in token_account_addresses.iter().enumerate() {
+            if token_account_addresses[..i].contains(token_account_address) {
+                return Err(SwapError::InvalidTokenAccount);
+            }
+        }
+        if amplification_coefficient == 0 {
+            return Err(SwapError::InvalidAmplificationCoefficient);
+        }
+        if fee_numerator > PoolParameter::FEE_DENOMINATOR
+            || admin_fee_numerator > PoolParameter::FEE_DENOMINATOR
+        {
+            return Err(SwapError::InvalidFee);
+        }
+
+        Ok(SwapV2 {
+            is_initialized: true,
+            nonce,
+            amplification_coefficient,
+            fee_numerator,
+            admin_fee_numerator,
+            precision_factor,
+            precision_multipliers,
+            token_account_addresses,
+            pool_mint_address,
+            admin_token_mint_address,
+            admin_settings,
+        })
+    }
+
+    /// Number of coins in the pool
+    pub fn n_coins(&self) -> usize {
+        self.token_account_addresses.len()
+    }
+
+    /// Token accounts of the pool, in the order every builder expects them in
+    pub fn token_accounts(&self) -> &[Pubkey] {
+        &self.token_account_addresses
+    }
+
+    /// Mint of the pool token
+    pub fn pool_mint(&self) -> &Pubkey {
+        &self.pool_mint_address
+    }
+
+    /// Mint of the admin token
+    pub fn admin_mint(&self) -> &Pubkey {
+        &self.admin_token_mint_address
+    }
+
+    /// Fails with [ProgramError::UninitializedAccount] if the swap is not initialized
+    pub fn ensure_initialized(&self) -> Result<(), ProgramError> {
+        if !self.is_initialized {
+            return Err(ProgramError::UninitializedAccount);
         }
+        Ok(())
     }
+
+    /// Packs the swap into a stack array rather than a caller provided slice
+    pub fn pack_to_array(&self) -> [u8; SwapV2::LEN] {
+        let mut dst = [0u8; SwapV2::LEN];
+        self.pack_into_slice(&mut dst);
+        dst
+    }
+
+    /// Checks that every precision multiplier is a power of ten, to be called after unpacking
+    /// state that may not have been written by the program
+    pub fn validate_precision(&self) -> Result<(), SwapError> {
+        for &multiplier in self.precision_multipliers.iter() {
+            let mut remainder = multiplier;
+            while remainder >= 10 && remainder % 10 == 0 {
+                remainder /= 10;
+            }
+            if remainder != 1 {
+                return Err(SwapError::InvalidPrecision);
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks that the precision factor is the largest precision multiplier, the one of the
+    /// coin with the fewest decimals
+    pub fn validate_precision_factor(&self) -> Result<(), SwapError> {
+        match self.precision_multipliers.iter().max() {
+            Some(&max_multiplier) if max_multiplier == self.precision_factor => Ok(()),
+            _ => Err(SwapError::InvalidPrecision),
+        }
+    }
+}
+
+/// Loads the swap state of an account owned by the program, failing on any other owner or on
+/// an uninitialized swap. Both versions are read as [SwapV2], the cached authority of a
+/// [SwapV3] being available through [SwapVersion::unpack].
+pub fn load_swap_state(
+    account_data: &[u8],
+    account_owner: &Pubkey,
+    program_id: &Pubkey,
+) -> Result<SwapV2, ProgramError> {
+    if account_owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    let swap = match SwapVersion::unpack(account_data)? {
+        SwapVersion::SwapV2(swap) => swap,
+        SwapVersion::SwapV3(swap) => swap.into_swap(),
+    };
+    swap.ensure_initialized()?;
+    Ok(swap)
+}
+
+/// A mint held by both pools, for routing a swap through one then the other. The state does
+/// not record mints, so each pool comes with the mints of its token accounts in state order;
+/// mismatched lists share nothing.
+pub fn shared_coin(
+    a: &SwapV2,
+    a_mints: &[Pubkey],
+    b: &SwapV2,
+    b_mints: &[Pubkey],
+) -> Option<Pubkey> {
+    if a_mints.len() != a.n_coins() || b_mints.len() != b.n_coins() {
+        return None;
+    }
+    a_mints.iter().find(|mint| b_mints.contains(mint)).copied()
 }
 
 impl Sealed for SwapV2 {}
@@ -93,7 +337,6 @@ impl IsInitialized for SwapV2 {
     }
 }
 
-// Please note how this is very similar to SwapV1, when V3 is introduced, we can delete V1 and migrate from V2 to V3
 impl Pack for SwapV2 {
     const LEN: usize = 1
         + 1
@@ -145,14 +388,20 @@ impl Pack for SwapV2 {
             _ => return Err(ProgramError::InvalidAccountData),
         };
 
+        // the length is read from account data, it must not index past the coin slots
+        let tokens_len = u32::from_le_bytes(*tokens_len) as usize;
+        if tokens_len > PoolParameter::MAX_N_COINS {
+            return Err(ProgramError::InvalidAccountData);
+        }
+
         let mut precision_multipliers = Vec::with_capacity(PoolParameter::MAX_N_COINS);
-        for i in 0..u32::from_le_bytes(*tokens_len) as usize {
+        for i in 0..tokens_len {
             let multiplier = array_ref!(multipliers, i * 8, 8);
             precision_multipliers.push(u64::from_le_bytes(*multiplier));
         }
 
         let mut token_account_addresses = Vec::with_capacity(PoolParameter::MAX_N_COINS);
-        for i in 0..u32::from_le_bytes(*tokens_len) as usize {
+        for i in 0..tokens_len {
             let token = array_ref!(tokens, i * 32, 32);
             token_account_addresses.push(Pubkey::new_from_array(*token));
         }
@@ -168,18 +417,7 @@ impl Pack for SwapV2 {
             token_account_addresses,
             pool_mint_address: Pubkey::new_from_array(*pool_mint),
             admin_token_mint_address: Pubkey::new_from_array(*admin_token_mint),
-            admin_settings: AdminSettings {
-                swap_enabled: match admin_settings[0] {
-                    0 => false,
-                    1 => true,
-                    _ => return Err(ProgramError::InvalidAccountData),
-                },
-                add_liquidity_enabled: match admin_settings[1] {
-                    0 => false,
-                    1 => true,
-                    _ => return Err(ProgramError::InvalidAccountData),
-                },
-            },
+            admin_settings: AdminSettings::try_from(&admin_settings[..])?,
         })
     }
 
@@ -228,7 +466,7 @@ impl Pack for SwapV2 {
             admin_settings,
         } = self;
 
-        is_initialized_dst[0] = *is_initialized as u8;
+        is_initialized_dst[0] = utils::bool_to_u8(*is_initialized);
         nonce_dst[0] = *nonce;
         amplification_coefficient_dst
             .copy_from_slice(u64::to_le_bytes(*amplification_coefficient).as_ref());
@@ -239,19 +477,616 @@ impl Pack for SwapV2 {
             .copy_from_slice(u32::to_le_bytes(token_account_addresses.len() as u32).as_ref());
 
         precision_factor_dst.copy_from_slice(u64::to_le_bytes(*precision_factor).as_ref());
-        for i in 0..precision_multipliers.len() {
+
+        // Unused coin slots are zeroed so the packed state does not depend on the buffer content
+        precision_multipliers_dst.fill(0);
+        token_account_addresses_dst.fill(0);
+
+        for (i, precision_multiplier) in precision_multipliers.iter().enumerate() {
             let multiplier_dst = array_mut_ref![precision_multipliers_dst, i * 8, 8];
-            multiplier_dst.copy_from_slice(u64::to_le_bytes(precision_multipliers[i]).as_ref());
+            multiplier_dst.copy_from_slice(u64::to_le_bytes(*precision_multiplier).as_ref());
         }
 
-        for i in 0..token_account_addresses.len() {
+        for (i, token_account_address) in token_account_addresses.iter().enumerate() {
             let token_address_dst = array_mut_ref![token_account_addresses_dst, i * 32, 32];
-            token_address_dst.copy_from_slice(token_account_addresses[i].as_ref());
+            token_address_dst.copy_from_slice(token_account_address.as_ref());
         }
 
         pool_mint_address_dst.copy_from_slice(pool_mint_address.as_ref());
         admin_token_mint_address_dst.copy_from_slice(admin_token_mint_address.as_ref());
-        admin_settings_dst[0] = admin_settings.swap_enabled as u8;
-        admin_settings_dst[1] = admin_settings.add_liquidity_enabled as u8;
+        admin_settings_dst.copy_from_slice(&admin_settings.to_bytes());
+    }
+}
+
+impl SwapV3 {
+    /// Migrates a [SwapV2], deriving the authority from its nonce
+    pub fn from_v2(
+        swap: SwapV2,
+        swap_account_address: &Pubkey,
+        program_id: &Pubkey,
+    ) -> Result<Self, ProgramError> {
+        let authority = utils::derive_pool_authority(program_id, swap_account_address, swap.nonce)?;
+        Ok(SwapV3 { swap, authority })
+    }
+
+    /// The swap authority, derived from the nonce
+    pub fn authority(&self) -> &Pubkey {
+        &self.authority
+    }
+
+    /// The fields shared with [SwapV2]
+    pub fn swap(&self) -> &SwapV2 {
+        &self.swap
+    }
+
+    /// Drops the cached authority, returning the fields shared with [SwapV2]
+    pub fn into_swap(self) -> SwapV2 {
+        self.swap
+    }
+}
+
+impl Sealed for SwapV3 {}
+
+impl IsInitialized for SwapV3 {
+    fn is_initialized(&self) -> bool {
+        self.swap.is_initialized
+    }
+}
+
+// SwapV3 is the SwapV2 layout followed by the cached authority
+impl Pack for SwapV3 {
+    const LEN: usize = SwapV2::LEN + 32;
+
+    fn unpack_from_slice(src: &[u8]) -> Result<Self, ProgramError> {
+        let src = array_ref![src, 0, SwapV3::LEN];
+        let (swap, authority) = array_refs![src, SwapV2::LEN, 32];
+
+        Ok(SwapV3 {
+            swap: SwapV2::unpack_from_slice(swap)?,
+            authority: Pubkey::new_from_array(*authority),
+        })
+    }
+
+    fn pack_into_slice(&self, dst: &mut [u8]) {
+        let dst = array_mut_ref![dst, 0, SwapV3::LEN];
+        let (swap_dst, authority_dst) = mut_array_refs![dst, SwapV2::LEN, 32];
+
+        self.swap.pack_into_slice(swap_dst);
+        authority_dst.copy_from_slice(self.authority.as_ref());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    fn pubkey() -> impl Strategy<Value = Pubkey> {
+        any::<[u8; 32]>().prop_map(Pubkey::new_from_array)
+    }
+
+    /// Initialized swaps of 1 to [PoolParameter::MAX_N_COINS] coins, with power of ten
+    /// multipliers of up to 1e12
+    fn swap_v2() -> impl Strategy<Value = SwapV2> {
+        (1..=PoolParameter::MAX_N_COINS)
+            .prop_flat_map(|n_coins| {
+                (
+                    any::<u8>(),
+                    1..=1_000_000u64,
+                    0..=PoolParameter::FEE_DENOMINATOR,
+                    0..=PoolParameter::FEE_DENOMINATOR,
+                    prop::collection::vec((0..=12u32).prop_map(|exp| 10u64.pow(exp)), n_coins),
+                    prop::collection::vec(pubkey(), n_coins),
+                    pubkey(),
+                    pubkey(),
+                    any::<(bool, bool)>(),
+                )
+            })
+            .prop_map(
+                |(
+                    nonce,
+                    amplification_coefficient,
+                    fee_numerator,
+                    admin_fee_numerator,
+                    precision_multipliers,
+                    token_account_addresses,
+                    pool_mint_address,
+                    admin_token_mint_address,
+                    (swap_enabled, add_liquidity_enabled),
+                )| SwapV2 {
+                    is_initialized: true,
+                    nonce,
+                    amplification_coefficient,
+                    fee_numerator,
+                    admin_fee_numerator,
+                    precision_factor: *precision_multipliers.iter().max().unwrap(),
+                    precision_multipliers,
+                    token_account_addresses,
+                    pool_mint_address,
+                    admin_token_mint_address,
+                    admin_settings: AdminSettings {
+                        swap_enabled,
+                        add_liquidity_enabled,
+                    },
+                },
+            )
+    }
+
+    fn swap_v3() -> impl Strategy<Value = SwapV3> {
+        (swap_v2(), pubkey()).prop_map(|(swap, authority)| SwapV3 { swap, authority })
+    }
+
+    proptest! {
+        #[test]
+        fn test_swap_v2_pack_unpack_round_trip(swap in swap_v2()) {
+            let mut packed = vec![0u8; SwapV2::LEN];
+            SwapV2::pack(swap.clone(), &mut packed)?;
+            prop_assert_eq!(SwapV2::unpack(&packed)?, swap);
+        }
+
+        #[test]
+        fn test_swap_v2_pack_clears_unused_slots(swap in swap_v2()) {
+            let mut packed = vec![0xffu8; SwapV2::LEN];
+            SwapV2::pack(swap.clone(), &mut packed)?;
+            let mut zeroed = vec![0u8; SwapV2::LEN];
+            SwapV2::pack(swap, &mut zeroed)?;
+            prop_assert_eq!(packed, zeroed);
+        }
+
+        #[test]
+        fn test_swap_v3_pack_unpack_round_trip(swap in swap_v3()) {
+            let mut packed = vec![0u8; SwapV3::LEN];
+            SwapV3::pack(swap.clone(), &mut packed)?;
+            prop_assert_eq!(SwapV3::unpack(&packed)?, swap);
+        }
+    }
+
+    fn initialized_swap_v2(nonce: u8) -> SwapV2 {
+        SwapV2::try_new(
+            nonce,
+            100,
+            4_000_000,
+            0,
+            1_000,
+            vec![1_000, 1],
+            vec![Pubkey::new_unique(), Pubkey::new_unique()],
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            AdminSettings::default(),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_from_v2_stores_derived_authority() {
+        let swap_account = Pubkey::new_unique();
+        let pdas = utils::pool_pdas(&crate::id(), &swap_account);
+        let swap =
+            SwapV3::from_v2(initialized_swap_v2(pdas.nonce), &swap_account, &crate::id()).unwrap();
+
+        assert_eq!(*swap.authority(), pdas.authority);
+        assert_eq!(
+            *swap.authority(),
+            utils::derive_pool_authority(&crate::id(), &swap_account, swap.swap().nonce).unwrap()
+        );
+
+        let mut packed = vec![0u8; SwapVersion::LATEST_LEN];
+        SwapVersion::pack(SwapVersion::SwapV3(swap), &mut packed).unwrap();
+        match SwapVersion::unpack(&packed).unwrap() {
+            SwapVersion::SwapV3(unpacked) => assert_eq!(*unpacked.authority(), pdas.authority),
+            SwapVersion::SwapV2(_) => panic!("expected the latest version"),
+        }
+    }
+
+    #[test]
+    fn test_load_swap_state_either_version() {
+        let program_id = crate::id();
+        let swap = initialized_swap_v2(255);
+        for packed in packed_versions(swap.clone()) {
+            assert_eq!(
+                load_swap_state(&packed, &program_id, &program_id),
+                Ok(swap.clone())
+            );
+        }
+    }
+
+    #[test]
+    fn test_load_swap_state_wrong_owner() {
+        let program_id = crate::id();
+        for packed in packed_versions(initialized_swap_v2(255)) {
+            assert_eq!(
+                load_swap_state(&packed, &Pubkey::new_unique(), &program_id),
+                Err(ProgramError::IncorrectProgramId)
+            );
+        }
+    }
+
+    #[test]
+    fn test_load_swap_state_uninitialized() {
+        let program_id = crate::id();
+        let swap = SwapV2 {
+            is_initialized: false,
+            ..initialized_swap_v2(255)
+        };
+        for packed in packed_versions(swap) {
+            assert_eq!(
+                load_swap_state(&packed, &program_id, &program_id),
+                Err(ProgramError::UninitializedAccount)
+            );
+        }
+        // a freshly allocated account has a zero version byte
+        assert_eq!(
+            load_swap_state(&[0u8; SwapVersion::LATEST_LEN], &program_id, &program_id),
+            Err(ProgramError::UninitializedAccount)
+        );
+    }
+
+    #[test]
+    fn test_load_swap_state_malformed() {
+        let program_id = crate::id();
+        let mut packed = packed_version(SwapVersion::SwapV3(SwapV3 {
+            swap: initialized_swap_v2(255),
+            authority: Pubkey::new_unique(),
+        }));
+        assert_eq!(
+            load_swap_state(&packed[..packed.len() - 1], &program_id, &program_id),
+            Err(ProgramError::InvalidAccountData)
+        );
+        assert_eq!(
+            load_swap_state(&[], &program_id, &program_id),
+            Err(ProgramError::InvalidAccountData)
+        );
+        packed[0] = 1;
+        assert_eq!(
+            load_swap_state(&packed, &program_id, &program_id),
+            Err(SwapError::UnsupportedVersion.into())
+        );
+    }
+
+    /// Offset of the coin count in a packed [SwapVersion], after the version byte
+    const TOKENS_LEN_OFFSET: usize = 1 + 1 + 1 + 8 + 8 + 8;
+
+    fn packed_version(swap: SwapVersion) -> Vec<u8> {
+        let len = match swap {
+            SwapVersion::SwapV2(_) => 1 + SwapV2::LEN,
+            SwapVersion::SwapV3(_) => SwapVersion::LATEST_LEN,
+        };
+        let mut packed = vec![0u8; len];
+        SwapVersion::pack(swap, &mut packed).unwrap();
+        packed
+    }
+
+    #[test]
+    fn test_is_initialized_empty_input() {
+        assert!(!SwapVersion::is_initialized(&[]));
+    }
+
+    fn packed_versions(swap: SwapV2) -> [Vec<u8>; 2] {
+        [
+            packed_version(SwapVersion::SwapV2(swap.clone())),
+            packed_version(SwapVersion::SwapV3(SwapV3 {
+                swap,
+                authority: Pubkey::new_unique(),
+            })),
+        ]
+    }
+
+    #[test]
+    fn test_is_initialized_initialized_input() {
+        for packed in packed_versions(initialized_swap_v2(255)) {
+            assert!(SwapVersion::is_initialized(&packed));
+            assert!(SwapVersion::unpack(&packed).unwrap().initialized_flag());
+        }
+    }
+
+    #[test]
+    fn test_is_initialized_uninitialized_input() {
+        let swap = SwapV2 {
+            is_initialized: false,
+            ..initialized_swap_v2(255)
+        };
+        for packed in packed_versions(swap) {
+            assert!(!SwapVersion::is_initialized(&packed));
+            assert_eq!(
+                SwapVersion::unpack(&packed),
+                Err(ProgramError::UninitializedAccount)
+            );
+        }
+        assert!(!SwapVersion::is_initialized(
+            &[0u8; SwapVersion::LATEST_LEN]
+        ));
+    }
+
+    #[test]
+    fn test_unpack_corrupt_tokens_len() {
+        let mut packed = packed_version(SwapVersion::SwapV3(SwapV3 {
+            swap: initialized_swap_v2(255),
+            authority: Pubkey::new_unique(),
+        }));
+        let tokens_len = PoolParameter::MAX_N_COINS as u32 + 1;
+        packed[TOKENS_LEN_OFFSET..TOKENS_LEN_OFFSET + 4].copy_from_slice(&tokens_len.to_le_bytes());
+
+        assert_eq!(
+            SwapVersion::unpack(&packed),
+            Err(ProgramError::InvalidAccountData)
+        );
+        assert!(!SwapVersion::is_initialized(&packed));
+    }
+
+    #[test]
+    fn test_read_nonce_matches_unpack() {
+        for packed in packed_versions(initialized_swap_v2(42)) {
+            let nonce = match SwapVersion::unpack(&packed).unwrap() {
+                SwapVersion::SwapV2(swap) => swap.nonce,
+                SwapVersion::SwapV3(swap) => swap.swap().nonce,
+            };
+            assert_eq!(nonce, 42);
+            assert_eq!(SwapVersion::read_nonce(&packed), Ok(nonce));
+        }
+    }
+
+    #[test]
+    fn test_read_nonce_invalid_input() {
+        assert_eq!(
+            SwapVersion::read_nonce(&[]),
+            Err(ProgramError::InvalidAccountData)
+        );
+        assert_eq!(
+            SwapVersion::read_nonce(&[StateVersion::V2 as u8, 1]),
+            Err(ProgramError::InvalidAccountData)
+        );
+        assert_eq!(
+            SwapVersion::read_nonce(&[0, 1, 42]),
+            Err(ProgramError::UninitializedAccount)
+        );
+        assert_eq!(
+            SwapVersion::read_nonce(&[1, 1, 42]),
+            Err(SwapError::UnsupportedVersion.into())
+        );
+    }
+
+    #[test]
+    fn test_validate_precision() {
+        let mut swap = initialized_swap_v2(255);
+        swap.precision_multipliers = vec![1, 1000];
+        assert_eq!(swap.validate_precision(), Ok(()));
+
+        swap.precision_multipliers = vec![3];
+        assert_eq!(swap.validate_precision(), Err(SwapError::InvalidPrecision));
+    }
+
+    #[test]
+    fn test_admin_settings_try_from() {
+        assert_eq!(
+            AdminSettings::try_from(&[1u8, 0][..]),
+            Ok(AdminSettings {
+                swap_enabled: true,
+                add_liquidity_enabled: false,
+            })
+        );
+        assert_eq!(
+            AdminSettings::try_from(&[0u8, 2][..]),
+            Err(ProgramError::InvalidAccountData)
+        );
+        for input in [&[][..], &[1][..], &[1, 1, 1][..]] {
+            assert_eq!(
+                AdminSettings::try_from(input),
+                Err(ProgramError::InvalidAccountData)
+            );
+        }
+    }
+
+    #[test]
+    fn test_admin_settings_bytes_round_trip() {
+        for (swap_enabled, add_liquidity_enabled) in
+            [(false, false), (false, true), (true, false), (true, true)]
+        {
+            let settings = AdminSettings {
+                swap_enabled,
+                add_liquidity_enabled,
+            };
+            let bytes = settings.to_bytes();
+            assert_eq!(bytes, [swap_enabled as u8, add_liquidity_enabled as u8]);
+            assert_eq!(AdminSettings::try_from(&bytes[..]), Ok(settings));
+        }
+    }
+
+    fn try_new_with(
+        amplification_coefficient: u64,
+        fee_numerator: u64,
+        admin_fee_numerator: u64,
+        precision_multipliers: Vec<u64>,
+        token_account_addresses: Vec<Pubkey>,
+    ) -> Result<SwapV2, SwapError> {
+        SwapV2::try_new(
+            255,
+            amplification_coefficient,
+            fee_numerator,
+            admin_fee_numerator,
+            1_000,
+            precision_multipliers,
+            token_account_addresses,
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            AdminSettings::default(),
+        )
+    }
+
+    fn distinct_accounts(n_coins: usize) -> Vec<Pubkey> {
+        (0..n_coins).map(|_| Pubkey::new_unique()).collect()
+    }
+
+    #[test]
+    fn test_try_new_n_coins() {
+        assert_eq!(
+            try_new_with(100, 0, 0, vec![1, 1_000], distinct_accounts(3)),
+            Err(SwapError::InvalidNCoins)
+        );
+        let n_coins = PoolParameter::MAX_N_COINS + 1;
+        assert_eq!(
+            try_new_with(100, 0, 0, vec![1; n_coins], distinct_accounts(n_coins)),
+            Err(SwapError::InvalidNCoins)
+        );
+    }
+
+    #[test]
+    fn test_try_new_too_few_coins() {
+        for n_coins in 0..PoolParameter::MIN_N_COINS {
+            assert_eq!(
+                try_new_with(100, 0, 0, vec![1; n_coins], distinct_accounts(n_coins)),
+                Err(SwapError::InvalidNCoins)
+            );
+        }
+        let n_coins = PoolParameter::MIN_N_COINS;
+        assert!(try_new_with(100, 0, 0, vec![1; n_coins], distinct_accounts(n_coins)).is_ok());
+    }
+
+    #[test]
+    fn test_try_new_duplicated_token_account() {
+        let account = Pubkey::new_unique();
+        assert_eq!(
+            try_new_with(100, 0, 0, vec![1, 1], vec![account, account]),
+            Err(SwapError::InvalidTokenAccount)
+        );
+    }
+
+    #[test]
+    fn test_try_new_zero_amplification_coefficient() {
+        assert_eq!(
+            try_new_with(0, 0, 0, vec![1, 1], distinct_accounts(2)),
+            Err(SwapError::InvalidAmplificationCoefficient)
+        );
+    }
+
+    #[test]
+    fn test_try_new_fees() {
+        let denominator = PoolParameter::FEE_DENOMINATOR;
+        assert!(try_new_with(
+            100,
+            denominator,
+            denominator,
+            vec![1, 1],
+            distinct_accounts(2)
+        )
+        .is_ok());
+        assert_eq!(
+            try_new_with(100, denominator + 1, 0, vec![1, 1], distinct_accounts(2)),
+            Err(SwapError::InvalidFee)
+        );
+        assert_eq!(
+            try_new_with(100, 0, denominator + 1, vec![1, 1], distinct_accounts(2)),
+            Err(SwapError::InvalidFee)
+        );
+    }
+
+    #[test]
+    fn test_ensure_initialized() {
+        let mut swap = initialized_swap_v2(255);
+        assert_eq!(swap.ensure_initialized(), Ok(()));
+
+        swap.is_initialized = false;
+        assert_eq!(
+            swap.ensure_initialized(),
+            Err(ProgramError::UninitializedAccount)
+        );
+    }
+
+    #[test]
+    fn test_pack_to_array_matches_pack_into_slice() {
+        let swap = initialized_swap_v2(255);
+        let mut packed = vec![0xffu8; SwapV2::LEN];
+        swap.pack_into_slice(&mut packed);
+        assert_eq!(swap.pack_to_array()[..], packed[..]);
+    }
+
+    #[test]
+    fn test_split_version() {
+        let packed = packed_version(SwapVersion::SwapV2(initialized_swap_v2(255)));
+        let (version, rest) = SwapVersion::split_version(&packed).unwrap();
+        assert_eq!(version, StateVersion::V2 as u8);
+        assert_eq!(rest, &packed[1..]);
+        assert!(SwapVersion::supported_version(version));
+    }
+
+    #[test]
+    fn test_split_version_empty_input() {
+        assert_eq!(
+            SwapVersion::split_version(&[]),
+            Err(ProgramError::InvalidAccountData)
+        );
+    }
+
+    #[test]
+    fn test_validate_precision_factor() {
+        let mut swap = initialized_swap_v2(255);
+        swap.precision_multipliers = vec![1, 1_000, 1_000_000];
+        swap.precision_factor = 1_000_000;
+        assert_eq!(swap.validate_precision_factor(), Ok(()));
+
+        swap.precision_factor = 1_000;
+        assert_eq!(
+            swap.validate_precision_factor(),
+            Err(SwapError::InvalidPrecision)
+        );
+        swap.precision_multipliers = vec![];
+        assert_eq!(
+            swap.validate_precision_factor(),
+            Err(SwapError::InvalidPrecision)
+        );
+    }
+
+    #[test]
+    fn test_state_version_try_from() {
+        assert_eq!(
+            StateVersion::try_from(0),
+            Err(ProgramError::UninitializedAccount)
+        );
+        assert_eq!(
+            StateVersion::try_from(1),
+            Err(SwapError::UnsupportedVersion.into())
+        );
+        assert_eq!(StateVersion::try_from(2), Ok(StateVersion::V2));
+        assert_eq!(StateVersion::try_from(3), Ok(StateVersion::V3));
+        assert_eq!(StateVersion::LATEST, StateVersion::V3);
+
+        for (version, supported) in [(0, false), (1, false), (2, true), (3, true), (4, false)] {
+            assert_eq!(SwapVersion::supported_version(version), supported);
+        }
+    }
+
+    #[test]
+    fn test_read_accessors() {
+        for n_coins in PoolParameter::MIN_N_COINS..=PoolParameter::MAX_N_COINS {
+            let swap =
+                try_new_with(100, 0, 0, vec![1; n_coins], distinct_accounts(n_coins)).unwrap();
+            assert_eq!(swap.token_accounts().len(), swap.n_coins());
+            assert_eq!(swap.n_coins(), n_coins);
+            assert_eq!(swap.token_accounts(), &swap.token_account_addresses[..]);
+            assert_eq!(swap.pool_mint(), &swap.pool_mint_address);
+            assert_eq!(swap.admin_mint(), &swap.admin_token_mint_address);
+        }
+    }
+
+    #[test]
+    fn test_shared_coin() {
+        let a = initialized_swap_v2(255);
+        let b = initialized_swap_v2(255);
+        let [usdc, usdt, ust] = [(); 3].map(|_| Pubkey::new_unique());
+
+        assert_eq!(shared_coin(&a, &[usdc, usdt], &b, &[ust, usdt]), Some(usdt));
+        assert_eq!(
+            shared_coin(&a, &[usdc, usdt], &b, &[ust, Pubkey::new_unique()]),
+            None
+        );
+    }
+
+    #[test]
+    fn test_shared_coin_mismatched_mint_lists() {
+        let a = initialized_swap_v2(255);
+        let b = initialized_swap_v2(255);
+        let [usdc, usdt, ust] = [(); 3].map(|_| Pubkey::new_unique());
+
+        assert_eq!(shared_coin(&a, &[usdc], &b, &[usdc, ust]), None);
+        assert_eq!(shared_coin(&a, &[usdc, usdt], &b, &[usdc, ust, usdt]), None);
     }
 }
@@ -1,4 +1,16 @@
-use solana_program::program_error::ProgramError;
+use crate::error::SwapError;
+use crate::PoolParameter;
+use solana_program::{program_error::ProgramError, pubkey::Pubkey};
+use std::convert::TryFrom;
+
+/// Position of a coin in a pool of up to [PoolParameter::MAX_N_COINS] coins
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum CoinRole {
+    First,
+    Second,
+    Third,
+    Fourth,
+}
 
 /// Converts u8 to bool if u8 == 0 or u8 == 1
 pub fn u8_to_bool(num: u8) -> Result<bool, ProgramError> {
@@ -8,3 +20,122 @@ pub fn u8_to_bool(num: u8) -> Result<bool, ProgramError> {
         _ => Err(ProgramError::InvalidAccountData),
     }
 }
+
+/// Converts bool to u8, the inverse of [u8_to_bool]
+pub fn bool_to_u8(value: bool) -> u8 {
+    value as u8
+}
+
+/// Converts a u128 curve amount into a u64 token amount, failing if it does not fit
+pub fn u128_to_u64_amount(amount: u128) -> Result<u64, ProgramError> {
+    u64::try_from(amount).map_err(|_| SwapError::InvalidConversion.into())
+}
+
+/// Pads amounts with zeros up to n_coins, failing if there are already more amounts than coins
+pub fn pad_amounts(mut amounts: Vec<u64>, n_coins: usize) -> Result<Vec<u64>, ProgramError> {
+    if amounts.len() > n_coins {
+        return Err(SwapError::InvalidNCoins.into());
+    }
+    amounts.resize(n_coins, 0);
+    Ok(amounts)
+}
+
+/// Maps a coin index to its [CoinRole], if index < n_coins <= MAX_N_COINS
+pub fn coin_role(index: usize, n_coins: usize) -> Option<CoinRole> {
+    if index >= n_coins || n_coins > PoolParameter::MAX_N_COINS {
+        return None;
+    }
+    match index {
+        0 => Some(CoinRole::First),
+        1 => Some(CoinRole::Second),
+        2 => Some(CoinRole::Third),
+        3 => Some(CoinRole::Fourth),
+        _ => None,
+    }
+}
+
+/// Program derived addresses of a pool
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct PoolPdas {
+    /// The $authority owning the pool token accounts and the pool mint
+    pub authority: Pubkey,
+    /// The nonce to pass to [crate::instruction::initialize] along with the authority
+    pub nonce: u8,
+}
+
+/// Derives the program derived addresses of the pool held by swap_account
+pub fn pool_pdas(program_id: &Pubkey, swap_account: &Pubkey) -> PoolPdas {
+    let (authority, nonce) =
+        Pubkey::find_program_address(&[&swap_account.to_bytes()[..32]], program_id);
+    PoolPdas { authority, nonce }
+}
+
+/// Recomputes the pool authority from the nonce stored in the swap state
+pub fn derive_pool_authority(
+    program_id: &Pubkey,
+    swap_account: &Pubkey,
+    nonce: u8,
+) -> Result<Pubkey, ProgramError> {
+    Pubkey::create_program_address(&[&swap_account.to_bytes()[..32], &[nonce]], program_id)
+        .map_err(|_| SwapError::InvalidProgramAddress.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pad_amounts() {
+        assert_eq!(pad_amounts(vec![1, 2], 4), Ok(vec![1, 2, 0, 0]));
+        assert_eq!(pad_amounts(vec![], 2), Ok(vec![0, 0]));
+    }
+
+    #[test]
+    fn test_pad_amounts_exact_length() {
+        assert_eq!(pad_amounts(vec![1, 2, 3], 3), Ok(vec![1, 2, 3]));
+    }
+
+    #[test]
+    fn test_pad_amounts_over_length() {
+        assert_eq!(
+            pad_amounts(vec![1, 2, 3], 2),
+            Err(SwapError::InvalidNCoins.into())
+        );
+    }
+
+    #[test]
+    fn test_u128_to_u64_amount() {
+        assert_eq!(u128_to_u64_amount(u64::MAX as u128), Ok(u64::MAX));
+        assert_eq!(
+            u128_to_u64_amount(u64::MAX as u128 + 1),
+            Err(SwapError::InvalidConversion.into())
+        );
+        assert_eq!(u128_to_u64_amount(1_000_000_000), Ok(1_000_000_000));
+    }
+
+    #[test]
+    fn test_coin_role() {
+        assert_eq!(coin_role(0, 3), Some(CoinRole::First));
+        assert_eq!(coin_role(1, 3), Some(CoinRole::Second));
+        assert_eq!(coin_role(2, 3), Some(CoinRole::Third));
+        assert_eq!(coin_role(3, 4), Some(CoinRole::Fourth));
+    }
+
+    #[test]
+    fn test_coin_role_out_of_range() {
+        assert_eq!(coin_role(3, 3), None);
+        assert_eq!(coin_role(0, 0), None);
+        assert_eq!(coin_role(4, PoolParameter::MAX_N_COINS + 1), None);
+    }
+
+    #[test]
+    fn test_pool_pdas_matches_derive_pool_authority() {
+        let program_id = crate::id();
+        let swap_account = Pubkey::new_unique();
+        let pdas = pool_pdas(&program_id, &swap_account);
+        assert_eq!(
+            derive_pool_authority(&program_id, &swap_account, pdas.nonce),
+            Ok(pdas.authority)
+        );
+    }
+}